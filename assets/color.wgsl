@@ -1,20 +1,15 @@
 // Vertex shader
 
-struct Matrices {
-    world: mat4x4<f32>,
-    view_proj: mat4x4<f32>,
-};
-
-@group(0) @binding(0)
-var<uniform> matrices: Matrices;
-
-@group(1) @binding(0)
-var<uniform> color: vec3<f32>;
+#define MATRICES_GROUP 0
+#include "include/matrices.wgsl"
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
 }
 
+@group(1) @binding(0)
+var<uniform> color: vec3<f32>;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
 }
@@ -0,0 +1,21 @@
+// Projects an equirectangular panorama onto cubemap faces
+
+const PI: f32 = 3.14159265359;
+
+#include "include/cube_faces.wgsl"
+
+@group(0) @binding(0)
+var panorama: texture_2d<f32>;
+
+@group(0) @binding(1)
+var panorama_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = normalize(face_dir(in.face, in.uv));
+    let longitude = atan2(dir.z, dir.x);
+    let latitude = asin(clamp(dir.y, -1.0, 1.0));
+    let uv = vec2<f32>(longitude / (2.0 * PI) + 0.5, 0.5 - latitude / PI);
+    // Explicit level since derivatives jump across the longitude seam
+    return textureSampleLevel(panorama, panorama_sampler, uv, 0.0);
+}
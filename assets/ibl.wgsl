@@ -0,0 +1,131 @@
+// Precomputes image based lighting from an environment cubemap
+
+const PI: f32 = 3.14159265359;
+
+#include "include/cube_faces.wgsl"
+
+@group(0) @binding(0)
+var env: texture_cube<f32>;
+
+@group(0) @binding(1)
+var env_sampler: sampler;
+
+// Matches `PrefilterParams`
+struct PrefilterParams {
+    roughness: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> prefilter: PrefilterParams;
+
+// Rotates `v` from a space where `n` is the z axis
+fn tangent_to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Half vector around the z axis distributed following GGX
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = roughness * roughness * roughness * roughness;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+// Cosine weighted average of the environment over the hemisphere around each direction
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(face_dir(in.face, in.uv));
+    // Reading a blurrier mip level hides the gaps between samples
+    let lod = max(log2(f32(textureDimensions(env).x)) - 5.0, 0.0);
+    let step = 0.05;
+
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += step) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += step) {
+            let v = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let color = textureSampleLevel(env, env_sampler, tangent_to_world(v, n), lod).rgb;
+            irradiance += color * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+const PREFILTER_SAMPLE_COUNT: u32 = 256u;
+
+// Environment convolved with the GGX distribution of `roughness`, assuming the view direction
+// is the same as the normal
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let roughness = prefilter.roughness;
+    let n = normalize(face_dir(in.face, in.uv));
+    let env_size = f32(textureDimensions(env).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * env_size * env_size);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < PREFILTER_SAMPLE_COUNT; i++) {
+        let xi = hammersley(i, PREFILTER_SAMPLE_COUNT);
+        let h = tangent_to_world(importance_sample_ggx(xi, roughness), n);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // Unlikely samples stand for a larger solid angle, so they're read from a blurrier
+            // mip level to avoid bright spots
+            let pdf = distribution_ggx(max(dot(n, h), 0.0), roughness) / 4.0;
+            let sample_solid_angle = 1.0 / (f32(PREFILTER_SAMPLE_COUNT) * pdf + 0.0001);
+            let lod = select(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0, roughness == 0.0);
+            color += textureSampleLevel(env, env_sampler, l, max(lod, 0.0)).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / weight, 1.0);
+}
+
+const BRDF_SAMPLE_COUNT: u32 = 1024u;
+
+// Scale and bias to the Fresnel reflectance at normal incidence, indexed by the cosine between
+// normal and view direction (x) and roughness (y)
+@fragment
+fn fs_brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n_dot_v = in.uv.x;
+    let roughness = in.uv.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < BRDF_SAMPLE_COUNT; i++) {
+        let h = importance_sample_ggx(hammersley(i, BRDF_SAMPLE_COUNT), roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = l.z;
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0001);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let g_vis = geometry_smith(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    return vec4<f32>(vec2<f32>(scale, bias) / f32(BRDF_SAMPLE_COUNT), 0.0, 1.0);
+}
@@ -0,0 +1,130 @@
+// Single Rayleigh and Mie scattering in a spherical atmosphere, marched along view rays from a
+// point just above the ground. Distances are in kilometers.
+// Expects SKY_GROUP to be defined with the index of the bind group
+
+struct Sky {
+    // Towards the sun, normalized
+    sun_direction: vec3<f32>,
+    // Haziness of the air, 1 is a perfectly clear sky
+    turbidity: f32,
+    ground_color: vec3<f32>,
+    // Towards the moon, normalized
+    moon_direction: vec3<f32>,
+    // Added to the scattered sunlight, visible once the sun has set
+    night_color: vec3<f32>,
+}
+
+@group(SKY_GROUP) @binding(0)
+var<uniform> sky: Sky;
+
+const PI: f32 = 3.14159265359;
+const PLANET_RADIUS: f32 = 6360.0;
+const ATMOSPHERE_RADIUS: f32 = 6420.0;
+const EYE_HEIGHT: f32 = 0.5;
+const RAYLEIGH_SCATTERING: vec3<f32> = vec3<f32>(5.8e-3, 13.5e-3, 33.1e-3);
+const RAYLEIGH_SCALE_HEIGHT: f32 = 8.0;
+// Scaled by turbidity
+const MIE_SCATTERING: f32 = 4e-3;
+const MIE_SCALE_HEIGHT: f32 = 1.2;
+const MIE_G: f32 = 0.76;
+const SUN_INTENSITY: f32 = 20.0;
+// Cosine of the angular radius of the sun and moon disks
+const SUN_COS_RADIUS: f32 = 0.99996;
+const MOON_COS_RADIUS: f32 = 0.99996;
+const MOON_INTENSITY: f32 = 0.5;
+const VIEW_SAMPLES: i32 = 16;
+const LIGHT_SAMPLES: i32 = 8;
+
+// Distance along the ray to the far intersection with a sphere around the planet center,
+// negative if there is none
+fn sphere_exit(origin: vec3<f32>, dir: vec3<f32>, radius: f32) -> f32 {
+    let b = dot(origin, dir);
+    let c = dot(origin, origin) - radius * radius;
+    let d = b * b - c;
+    if d < 0.0 {
+        return -1.0;
+    }
+    return -b + sqrt(d);
+}
+
+// Distance along the ray to the ground, negative if it's missed
+fn ground_hit(origin: vec3<f32>, dir: vec3<f32>) -> f32 {
+    let b = dot(origin, dir);
+    let c = dot(origin, origin) - PLANET_RADIUS * PLANET_RADIUS;
+    let d = b * b - c;
+    if d < 0.0 || b > 0.0 {
+        return -1.0;
+    }
+    return -b - sqrt(d);
+}
+
+// Rayleigh and Mie densities relative to the ground level
+fn densities(pos: vec3<f32>) -> vec2<f32> {
+    let height = max(length(pos) - PLANET_RADIUS, 0.0);
+    return exp(-height / vec2<f32>(RAYLEIGH_SCALE_HEIGHT, MIE_SCALE_HEIGHT));
+}
+
+fn extinction(optical_depth: vec2<f32>, mie_scattering: f32) -> vec3<f32> {
+    // Mie extinction includes absorption by aerosols
+    return exp(-(RAYLEIGH_SCATTERING * optical_depth.x + 1.1 * mie_scattering * optical_depth.y));
+}
+
+// Optical depth from the point to the top of the atmosphere towards the sun
+fn sun_optical_depth(pos: vec3<f32>) -> vec2<f32> {
+    let len = sphere_exit(pos, sky.sun_direction, ATMOSPHERE_RADIUS);
+    let step = len / f32(LIGHT_SAMPLES);
+    var depth = vec2<f32>(0.0);
+    for (var i = 0; i < LIGHT_SAMPLES; i++) {
+        depth += densities(pos + sky.sun_direction * (f32(i) + 0.5) * step) * step;
+    }
+    return depth;
+}
+
+// Light scattered towards the eye from the direction, plus the ground below the horizon. The sun
+// and moon disks are left out unless `disks` is set.
+fn sky_color(dir: vec3<f32>, disks: bool) -> vec3<f32> {
+    let origin = vec3<f32>(0.0, PLANET_RADIUS + EYE_HEIGHT, 0.0);
+    let mie_scattering = MIE_SCATTERING * max(sky.turbidity, 1.0);
+
+    let ground = ground_hit(origin, dir);
+    let len = select(sphere_exit(origin, dir, ATMOSPHERE_RADIUS), ground, ground > 0.0);
+    let step = len / f32(VIEW_SAMPLES);
+
+    var rayleigh = vec3<f32>(0.0);
+    var mie = vec3<f32>(0.0);
+    var view_depth = vec2<f32>(0.0);
+    for (var i = 0; i < VIEW_SAMPLES; i++) {
+        let pos = origin + dir * (f32(i) + 0.5) * step;
+        let density = densities(pos) * step;
+        view_depth += density;
+        // Light reaching the point from the sun and then the eye
+        let transmittance = extinction(view_depth + sun_optical_depth(pos), mie_scattering);
+        rayleigh += density.x * transmittance;
+        mie += density.y * transmittance;
+    }
+
+    let mu = dot(dir, sky.sun_direction);
+    let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    let g2 = MIE_G * MIE_G;
+    let mie_phase = 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + mu * mu)
+        / ((2.0 + g2) * pow(1.0 + g2 - 2.0 * MIE_G * mu, 1.5));
+    var color = SUN_INTENSITY
+        * (rayleigh * RAYLEIGH_SCATTERING * rayleigh_phase + mie * mie_scattering * mie_phase)
+        + sky.night_color;
+
+    let view_transmittance = extinction(view_depth, mie_scattering);
+    if ground > 0.0 {
+        // Diffuse ground lit by the sun, seen through the air in front of it
+        let pos = origin + dir * ground;
+        let n_dot_l = max(dot(normalize(pos), sky.sun_direction), 0.0);
+        let sun = SUN_INTENSITY * extinction(sun_optical_depth(pos), mie_scattering);
+        color += sky.ground_color / PI * sun * n_dot_l * view_transmittance;
+    } else if disks && mu > SUN_COS_RADIUS {
+        color += SUN_INTENSITY * view_transmittance;
+    } else if disks && dot(dir, sky.moon_direction) > MOON_COS_RADIUS {
+        color += MOON_INTENSITY * view_transmittance;
+    }
+
+    // Exposure tone mapping, render targets are not HDR
+    return 1.0 - exp(-color);
+}
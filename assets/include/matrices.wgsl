@@ -0,0 +1,25 @@
+// Expects MATRICES_GROUP to be defined with the index of the bind group
+
+struct Matrices {
+    world: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    camera_position: vec3<f32>,
+#ifdef SKINNED
+    // Relative to `world`, see `MAX_JOINTS`
+    joints: array<mat4x4<f32>, 64>,
+#endif
+};
+
+@group(MATRICES_GROUP) @binding(0)
+var<uniform> matrices: Matrices;
+
+// Transforms normals by the inverse transpose of the upper 3x3 of `m`, so that they stay
+// perpendicular to surfaces under non-uniform scale. Computed from the cofactors, which only differ
+// by the determinant, since normals are normalized afterwards anyway.
+fn normal_matrix(m: mat4x4<f32>) -> mat3x3<f32> {
+    let x = m[0].xyz;
+    let y = m[1].xyz;
+    let z = m[2].xyz;
+    // Keeps normals facing outwards for mirroring transforms, whose determinant is negative
+    return mat3x3<f32>(cross(y, z), cross(z, x), cross(x, y)) * sign(dot(x, cross(y, z)));
+}
@@ -0,0 +1,12 @@
+// Matches `TexturedMaterial::VERTEX_ATTRIBUTES`, or `SKINNED_VERTEX_ATTRIBUTES` with SKINNED
+// defined. Locations are those of `VertexAttribute`.
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+#ifdef SKINNED
+    @location(6) joints: vec4<u32>,
+    @location(7) weights: vec4<f32>,
+#endif
+}
@@ -35,9 +35,78 @@ var texture: texture_2d<f32>;
 @group(0) @binding(1)
 var texSampler: sampler;
 
+@group(0) @binding(2)
+var depth_texture: texture_depth_2d;
+
+@group(0) @binding(3)
+var skybox: texture_cube<f32>;
+
+@group(0) @binding(4)
+var skybox_sampler: sampler;
+
+struct Fog {
+    // Of the camera that rendered `texture`
+    inv_view_proj: mat4x4<f32>,
+    camera_position: vec3<f32>,
+    // Extinction per unit of distance at `height`, 0 disables fog
+    density: f32,
+    // How quickly the density decreases above `height`, 0 for uniform fog
+    height_falloff: f32,
+    height: f32,
+    // Whether the sky is computed from `sky` rather than sampled from `skybox`
+    procedural_sky: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> fog: Fog;
+
+#define SKY_GROUP 2
+#include "include/atmosphere.wgsl"
+
+// Exponential height fog integrated along the ray from the camera to the point
+fn fog_amount(position: vec3<f32>) -> f32 {
+    let ray = position - fog.camera_position;
+    let distance = length(ray);
+    let falloff = fog.height_falloff * ray.y;
+    // Limit of (1 - e^-x) / x at 0 avoids dividing by 0 for level rays
+    let height_factor = select(1.0, (1.0 - exp(-falloff)) / falloff, abs(falloff) > 0.001);
+    let camera_density =
+        fog.density * exp(-fog.height_falloff * (fog.camera_position.y - fog.height));
+    return 1.0 - exp(-camera_density * distance * height_factor);
+}
+
+fn apply_fog(color: vec3<f32>, pixel: vec2<f32>) -> vec3<f32> {
+    let depth = textureLoad(depth_texture, vec2<i32>(pixel), 0);
+    // Nothing was drawn over the sky
+    if depth >= 1.0 || fog.density <= 0.0 {
+        return color;
+    }
+
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let ndc = vec2<f32>(pixel.x / size.x * 2.0 - 1.0, 1.0 - pixel.y / size.y * 2.0);
+    let world = fog.inv_view_proj * vec4<f32>(ndc, depth, 1.0);
+    let position = world.xyz / world.w;
+
+    // Far away fog takes the color of the sky at the horizon behind it. The offset keeps rays
+    // pointing straight down from normalizing a zero vector.
+    let ray = position - fog.camera_position;
+    let horizon = normalize(vec3<f32>(ray.x + 1e-6, 0.0, ray.z));
+    var fog_color: vec3<f32>;
+    if fog.procedural_sky != 0u {
+        fog_color = sky_color(horizon, false);
+    } else {
+        fog_color = textureSampleLevel(skybox, skybox_sampler, horizon, 0.0).rgb;
+    }
+
+    return mix(color, fog_color, fog_amount(position));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(texture, texSampler, in.tex_coords);
+    // The source texture is the same size as the target, so pixels match
+    let fogged = apply_fog(color.rgb, in.clip_position.xy);
     // Basic vignetting
     var m = max(0.0, 1.0 - length(in.tex_coords - vec2<f32>(0.5, 0.5)) / 1.0);
-    return textureSample(texture, texSampler, in.tex_coords) * m;
+    return vec4<f32>(fogged, color.a) * m;
 }
@@ -0,0 +1,47 @@
+// Procedural sky, see include/atmosphere.wgsl
+
+// Vertex shader
+
+struct Matrices {
+    view_rot: mat3x3<f32>,
+    proj_inv: mat4x4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> matrices: Matrices;
+
+struct VertexInput {
+    @location(0)
+    position: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position)
+    clip_position: vec4<f32>,
+
+    @location(0)
+    view_dir: vec3<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(in.position, 1.0);
+
+    var pos_unprojected = matrices.proj_inv * out.clip_position;
+    // Inverse of a rotation is its transpose
+    out.view_dir = transpose(matrices.view_rot) * pos_unprojected.xyz;
+
+    return out;
+}
+
+// Fragment shader
+
+#define SKY_GROUP 1
+#include "include/atmosphere.wgsl"
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sky_color(normalize(in.view_dir), true), 1.0);
+}
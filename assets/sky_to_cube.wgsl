@@ -0,0 +1,12 @@
+// Renders the procedural sky into cubemap faces, for image based lighting. The sun and moon
+// disks are left out since the direct light accounts for them.
+
+#include "include/cube_faces.wgsl"
+
+#define SKY_GROUP 0
+#include "include/atmosphere.wgsl"
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sky_color(normalize(face_dir(in.face, in.uv)), false), 1.0);
+}
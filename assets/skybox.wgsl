@@ -1,8 +1,8 @@
 // Vertex shader
 
 struct Matrices {
-    view_mat: mat4x4<f32>,
-    proj_mat_inv: mat4x4<f32>,
+    view_rot: mat3x3<f32>,
+    proj_inv: mat4x4<f32>,
 }
 
 @group(0) @binding(0)
@@ -27,11 +27,9 @@ fn vs_main(in: VertexInput) -> VertexOutput {
 
     out.clip_position =  vec4<f32>(in.position, 1.0);
 
-    var pos_unprojected = matrices.proj_mat_inv * out.clip_position;
-    // Couldn't pass a 3x3 matrix in the uniform so transforming it into 3x3 here.
-    // Also just using the raw 4x4 view matrix does not work because of its position component, apparently.
-    var view_mat_inv = transpose(mat3x3<f32>(matrices.view_mat.x.xyz, matrices.view_mat.y.xyz, matrices.view_mat.z.xyz));
-    out.uv = view_mat_inv * pos_unprojected.xyz;
+    var pos_unprojected = matrices.proj_inv * out.clip_position;
+    // Inverse of a rotation is its transpose
+    out.uv = transpose(matrices.view_rot) * pos_unprojected.xyz;
 
     return out;
 }
@@ -1,28 +1,44 @@
-// Vertex shader
+// Variants:
+// - LIT: diffuse lighting from a directional light plus ambient light from the environment
+// - SKINNED: vertices follow the joints of a skin, only used together with LIT
 
-struct Matrices {
-    world: mat4x4<f32>,
-    view_proj: mat4x4<f32>,
-};
+// Vertex shader
 
-@group(1) @binding(0)
-var<uniform> matrices: Matrices;
+#define MATRICES_GROUP 1
+#include "include/matrices.wgsl"
+#include "include/vertex_input.wgsl"
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>,
-}
+@group(2) @binding(0)
+var<uniform> uv_tiling: vec2<f32>;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+#ifdef LIT
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+#endif
 }
 
 @vertex
 fn vs_main(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.tex_coords = in.tex_coords;
-    out.clip_position = matrices.view_proj * matrices.world * vec4<f32>(in.position, 1.0);
+    out.tex_coords = in.tex_coords * uv_tiling;
+#ifdef SKINNED
+    let skin = matrices.joints[in.joints.x] * in.weights.x
+        + matrices.joints[in.joints.y] * in.weights.y
+        + matrices.joints[in.joints.z] * in.weights.z
+        + matrices.joints[in.joints.w] * in.weights.w;
+    let world = matrices.world * skin;
+#else
+    let world = matrices.world;
+#endif
+    let world_position = world * vec4<f32>(in.position, 1.0);
+    out.clip_position = matrices.view_proj * world_position;
+#ifdef LIT
+    out.normal = normal_matrix(world) * in.normal;
+    out.world_position = world_position.xyz;
+#endif
     return out;
 }
 
@@ -34,7 +50,57 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+#ifdef LIT
+// Precomputed from the skybox, see `Environment`
+@group(3) @binding(0)
+var irradiance_map: texture_cube<f32>;
+
+@group(3) @binding(1)
+var prefiltered_map: texture_cube<f32>;
+
+@group(3) @binding(2)
+var brdf_lut: texture_2d<f32>;
+
+@group(3) @binding(3)
+var env_sampler: sampler;
+
+struct Lighting {
+    light_direction: vec3<f32>,
+    environment_intensity: f32,
+    light_color: vec3<f32>,
+    ambient: vec3<f32>,
+}
+
+@group(3) @binding(4)
+var<uniform> lighting: Lighting;
+
+// Surfaces are treated as rough dielectrics
+const ROUGHNESS: f32 = 0.7;
+const F0: vec3<f32> = vec3<f32>(0.04);
+#endif
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    var color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+#ifdef LIT
+    let n = normalize(in.normal);
+    let v = normalize(matrices.camera_position - in.world_position);
+    let n_dot_v = max(dot(n, v), 0.0);
+
+    let diffuse = lighting.light_color * max(dot(n, lighting.light_direction), 0.0);
+
+    // Split sum approximation, with Fresnel adjusted for roughness
+    let fresnel = F0 + (max(vec3<f32>(1.0 - ROUGHNESS), F0) - F0) * pow(1.0 - n_dot_v, 5.0);
+    let irradiance = textureSample(irradiance_map, env_sampler, n).rgb;
+    let max_lod = f32(textureNumLevels(prefiltered_map) - 1u);
+    let prefiltered =
+        textureSampleLevel(prefiltered_map, env_sampler, reflect(-v, n), ROUGHNESS * max_lod).rgb;
+    let brdf = textureSample(brdf_lut, env_sampler, vec2<f32>(n_dot_v, ROUGHNESS)).rg;
+    let ambient = lighting.environment_intensity
+        * ((1.0 - fresnel) * irradiance * color.rgb + prefiltered * (fresnel * brdf.x + brdf.y))
+        + lighting.ambient * color.rgb;
+
+    color = vec4<f32>(color.rgb * diffuse + ambient, color.a);
+#endif
+    return color;
 }
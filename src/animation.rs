@@ -0,0 +1,337 @@
+use anyhow::*;
+
+use crate::components::Transform;
+use crate::math::{Quat, UnitQuat, Vec3, Vec4};
+
+// Keyframed node transforms, as imported from glTF. Rotations follow glTF rather than
+// `Transform`, i.e. they are not inverted.
+pub struct AnimationClip {
+    pub name: String,
+    // Time of the last keyframe
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: String, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .map(|c| *c.times.last().unwrap())
+            .fold(0.0, f32::max);
+        Self {
+            name,
+            duration,
+            channels,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // Each keyframe has an in-tangent, a value and an out-tangent
+    CubicSpline,
+}
+
+// Animates one property of a node. Translations and scales use the xyz of the values,
+// rotations are quaternions stored as xyzw.
+pub struct Channel {
+    // Index into the nodes of the model
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    times: Vec<f32>,
+    values: Vec<Vec4>,
+}
+
+impl Channel {
+    // Times must be increasing, with one value per time or three for cubic splines
+    pub fn new(
+        node: usize,
+        property: Property,
+        interpolation: Interpolation,
+        times: Vec<f32>,
+        values: Vec<Vec4>,
+    ) -> Result<Self> {
+        if times.is_empty() {
+            bail!("Channel has no keyframes");
+        }
+        if times.windows(2).any(|t| t[0] > t[1]) {
+            bail!("Keyframe times are not increasing");
+        }
+        let values_per_key = match interpolation {
+            Interpolation::CubicSpline => 3,
+            _ => 1,
+        };
+        if values.len() != times.len() * values_per_key {
+            bail!(
+                "Channel has {} values for {} keyframes",
+                values.len(),
+                times.len()
+            );
+        }
+
+        Ok(Self {
+            node,
+            property,
+            interpolation,
+            times,
+            values,
+        })
+    }
+
+    // Clamped to the first and last keyframes
+    pub fn sample(&self, time: f32) -> Vec4 {
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return self.value(0);
+        }
+        if next == self.times.len() {
+            return self.value(next - 1);
+        }
+
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / dt;
+        let (a, b) = (self.value(prev), self.value(next));
+        match self.interpolation {
+            Interpolation::Step => a,
+            Interpolation::Linear if self.property == Property::Rotation => {
+                let a = UnitQuat::new_normalize(Quat::from_vector(a));
+                let b = UnitQuat::new_normalize(Quat::from_vector(b));
+                a.interpolate(&b, t).coords
+            }
+            Interpolation::Linear => a.lerp(&b, t),
+            Interpolation::CubicSpline => {
+                let out_tangent = self.values[prev * 3 + 2] * dt;
+                let in_tangent = self.values[next * 3] * dt;
+                let (t2, t3) = (t * t, t * t * t);
+                let v = a * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + b * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2);
+                if self.property == Property::Rotation {
+                    v.normalize()
+                } else {
+                    v
+                }
+            }
+        }
+    }
+
+    fn value(&self, key: usize) -> Vec4 {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        }
+    }
+}
+
+// Shape of the transition into a keyframe of a `Track`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    // Holds the previous value until the keyframe is reached
+    Step,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // Maps the progress between two keyframes, from 0 to 1
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+pub trait Interpolate: Copy {
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+impl Interpolate for UnitQuat {
+    // Along the shortest path
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        let other = if self.coords.dot(&other.coords) < 0.0 {
+            UnitQuat::new_unchecked(-other.into_inner())
+        } else {
+            *other
+        };
+        self.try_slerp(&other, t, 1e-6).unwrap_or(other)
+    }
+}
+
+// Keyframed values of a single property, for animating entities from code
+#[derive(Clone)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+}
+
+#[derive(Clone)]
+struct Keyframe<T> {
+    time: f32,
+    value: T,
+    // How the value changes from the previous keyframe to this one
+    easing: Easing,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<T: Interpolate> Track<T> {
+    // Keyframes can be added in any order. The easing of the first one is unused.
+    pub fn key(mut self, time: f32, value: T, easing: Easing) -> Self {
+        let index = self.keys.partition_point(|k| k.time <= time);
+        self.keys.insert(
+            index,
+            Keyframe {
+                time,
+                value,
+                easing,
+            },
+        );
+        self
+    }
+
+    // Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map_or(0.0, |k| k.time)
+    }
+
+    // Clamped to the first and last keyframes, none without keyframes
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let next = self.keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keys.first().map(|k| k.value);
+        }
+        if next == self.keys.len() {
+            return Some(self.keys[next - 1].value);
+        }
+
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        Some(a.value.interpolate(&b.value, b.easing.apply(t)))
+    }
+}
+
+// Node transform made of its parts, for blending
+#[derive(Clone, Copy)]
+pub struct Pose {
+    pub translation: Vec3,
+    pub rotation: UnitQuat,
+    pub scale: Vec3,
+}
+
+impl Pose {
+    pub fn from_transform(transform: &Transform) -> Self {
+        Self {
+            translation: transform.position(),
+            // Transforms store the inverse rotation
+            rotation: transform.rotation().inverse(),
+            scale: transform.scale(),
+        }
+    }
+
+    pub fn to_transform(self) -> Transform {
+        let mut transform = Transform::new(self.translation, self.scale);
+        transform.set_rotation(self.rotation.inverse());
+        transform
+    }
+}
+
+// Weighted sum of poses. Parts no pose contributed to are taken from the rest pose, and so is
+// whatever weight is missing to reach 1.
+#[derive(Default)]
+pub struct PoseBlend {
+    translation: (Vec3, f32),
+    rotation: (Vec4, f32),
+    scale: (Vec3, f32),
+}
+
+impl PoseBlend {
+    pub fn add(&mut self, property: Property, value: Vec4, weight: f32) {
+        match property {
+            Property::Translation => {
+                self.translation.0 += value.xyz() * weight;
+                self.translation.1 += weight;
+            }
+            Property::Rotation => {
+                // q and -q are the same rotation, they must not cancel out
+                let sign = if self.rotation.0.dot(&value) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                self.rotation.0 += value * sign * weight;
+                self.rotation.1 += weight;
+            }
+            Property::Scale => {
+                self.scale.0 += value.xyz() * weight;
+                self.scale.1 += weight;
+            }
+        }
+    }
+
+    pub fn finish(self, rest: &Pose) -> Pose {
+        let blend = |(sum, weight): (Vec3, f32), rest: Vec3| {
+            (sum + rest * (1.0 - weight).max(0.0)) / weight.max(1.0)
+        };
+
+        // Averaging rotations shortens their sum, which would leave the rest pose more than its
+        // share, so the sum is brought back to the length of its weight first
+        let (sum, weight) = self.rotation;
+        let mut rotation = sum
+            .try_normalize(1e-6)
+            .map_or(Vec4::zeros(), |r| r * weight);
+        let rest_rotation = rest.rotation.coords;
+        if weight < 1.0 {
+            let sign = if rotation.dot(&rest_rotation) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            rotation += rest_rotation * sign * (1.0 - weight);
+        }
+
+        Pose {
+            translation: blend(self.translation, rest.translation),
+            rotation: UnitQuat::try_new(Quat::from_vector(rotation), 1e-6).unwrap_or(rest.rotation),
+            scale: blend(self.scale, rest.scale),
+        }
+    }
+}
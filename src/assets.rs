@@ -1,29 +1,72 @@
-use slotmap::{DefaultKey, SlotMap};
+use std::borrow::Cow;
 
-use crate::file;
+use anyhow::*;
+use slotmap::{DefaultKey, SecondaryMap, SlotMap};
+
+use crate::equirect::EquirectToCube;
 use crate::graphics::Graphics;
+use crate::ibl::{Environment, SkyCube};
 use crate::materials::{
-    ColorMaterial, Material, PostProcessMaterial, SkyboxMaterial, TexturedMaterial,
+    ColorMaterial, Material, PostProcessMaterial, SkyMaterial, SkyParams, SkyboxMaterial,
+    TexturedMaterial,
 };
-use crate::mesh::Mesh;
-use crate::texture::Texture;
+use crate::mesh::{Mesh, Normals};
+use crate::mipmaps::MipmapGenerator;
+use crate::obj;
+use crate::render_target::RenderTarget;
+use crate::shader_preprocessor;
+use crate::shader_reflection::ShaderReflection;
+use crate::texture::{SamplerOptions, Texture};
+use crate::watcher::AssetWatcher;
 
 pub type MeshHandle = DefaultKey;
 pub type MaterialHandle = DefaultKey;
 pub type ShaderHandle = DefaultKey;
 pub type TextureHandle = DefaultKey;
 
+struct Shader {
+    module: wgpu::ShaderModule,
+    reflection: ShaderReflection,
+}
+
+// What a shader module was built from
+struct ShaderSource {
+    file: String,
+    defines: Vec<String>,
+    // Files the module depends on, including the root file
+    files: Vec<String>,
+}
+
 pub struct Assets {
     pub bricks_texture: TextureHandle,
     pub crate_texture: TextureHandle,
     pub skybox_texture: TextureHandle,
     textures: SlotMap<TextureHandle, Texture>,
+    // Lighting from the skybox or the procedural sky, used by lit materials
+    pub environment: Environment,
+    sky_cube: SkyCube,
+    // Sky the environment was last rendered from, the skybox if none
+    environment_sky: Option<SkyParams>,
+    // Used when loading textures
+    mipmaps: MipmapGenerator,
+    equirect_to_cube: EquirectToCube,
 
     pub color_shader: ShaderHandle,
     pub textured_shader: ShaderHandle,
+    pub textured_lit_shader: ShaderHandle,
+    pub textured_skinned_shader: ShaderHandle,
     pub skybox_shader: ShaderHandle,
+    pub sky_shader: ShaderHandle,
     pub postprocess_shader: ShaderHandle,
-    shaders: SlotMap<ShaderHandle, wgpu::ShaderModule>,
+    // Used by the texture loading passes and the environment
+    mipmap_shader: ShaderHandle,
+    equirect_shader: ShaderHandle,
+    ibl_shader: ShaderHandle,
+    sky_to_cube_shader: ShaderHandle,
+    shaders: SlotMap<ShaderHandle, Shader>,
+    shader_sources: SecondaryMap<ShaderHandle, ShaderSource>,
+    // Used for hot reloading, missing if watching could not be started
+    watcher: Option<AssetWatcher>,
 
     pub box_mesh: MeshHandle,
     pub quad_mesh: MeshHandle,
@@ -34,68 +77,302 @@ pub struct Assets {
 
 impl Assets {
     pub fn load(gfx: &Graphics) -> Self {
-        let (
-            box_mesh,
-            skybox_tex,
-            bricks_tex,
-            crate_tex,
-            color_shader,
-            textured_shader,
-            postprocess_shader,
-            skybox_shader,
-        ) = pollster::block_on(async {
-            (
-                Mesh::from_file(gfx, "cube.obj").await,
-                Texture::new_cube_from_file("skybox_bgra.dds", gfx)
-                    .await
-                    .unwrap(),
-                Texture::new_2d_from_file("bricks.png", gfx).await.unwrap(),
-                Texture::new_2d_from_file("crate.png", gfx).await.unwrap(),
-                new_shader_module(gfx, "color.wgsl").await,
-                new_shader_module(gfx, "textured.wgsl").await,
-                new_shader_module(gfx, "post-process.wgsl").await,
-                new_shader_module(gfx, "skybox.wgsl").await,
-            )
-        });
-
+        // The cube has no materials, it is textured by the entities using it
+        let cube = pollster::block_on(obj::load("cube.obj", Normals::Flat)).unwrap();
+        let parts = cube
+            .parts
+            .into_iter()
+            .map(|p| (p.vertices, p.indices))
+            .collect::<Vec<_>>();
+        let box_mesh = Mesh::from_parts(gfx, &parts, true).unwrap();
         let mut meshes = SlotMap::new();
         let box_mesh = meshes.insert(box_mesh);
         let quad_mesh = meshes.insert(Mesh::new_quad(gfx));
 
         let mut shaders = SlotMap::new();
-        let color_shader = shaders.insert(color_shader);
-        let textured_shader = shaders.insert(textured_shader);
-        let postprocess_shader = shaders.insert(postprocess_shader);
-        let skybox_shader = shaders.insert(skybox_shader);
-
-        let mut textures = SlotMap::new();
-        let bricks_texture = textures.insert(bricks_tex);
-        let skybox_texture = textures.insert(skybox_tex);
-        let crate_texture = textures.insert(crate_tex);
-
-        Self {
-            textures,
-            bricks_texture,
-            crate_texture,
-            skybox_texture,
+        let mut shader_sources = SecondaryMap::new();
+        // Shaders are checked by the material or pass using them to declare the bindings it
+        // provides and only use its vertex attributes
+        let mut load_shader =
+            |file: &str, defines: &[&str], check: fn(&ShaderReflection) -> Result<()>| {
+                let (shader, files) = pollster::block_on(new_shader(gfx, file, defines))
+                    .and_then(|(shader, files)| {
+                        check(&shader.reflection)?;
+                        Ok((shader, files))
+                    })
+                    .with_context(|| format!("Invalid shader {file} {defines:?}"))
+                    .unwrap();
+                let handle = shaders.insert(shader);
+                shader_sources.insert(
+                    handle,
+                    ShaderSource {
+                        file: file.to_string(),
+                        defines: defines.iter().map(|d| d.to_string()).collect(),
+                        files,
+                    },
+                );
+                handle
+            };
+        let color_shader = load_shader("color.wgsl", &[], ColorMaterial::check_shader);
+        let textured_shader = load_shader("textured.wgsl", &[], TexturedMaterial::check_shader);
+        let textured_lit_shader = load_shader(
+            "textured.wgsl",
+            &["LIT"],
+            TexturedMaterial::check_lit_shader,
+        );
+        let textured_skinned_shader = load_shader(
+            "textured.wgsl",
+            &["LIT", "SKINNED"],
+            TexturedMaterial::check_skinned_shader,
+        );
+        let postprocess_shader =
+            load_shader("post-process.wgsl", &[], PostProcessMaterial::check_shader);
+        let skybox_shader = load_shader("skybox.wgsl", &[], SkyboxMaterial::check_shader);
+        let sky_shader = load_shader("sky.wgsl", &[], SkyMaterial::check_shader);
+        let mipmap_shader = load_shader("mipmap.wgsl", &[], MipmapGenerator::check_shader);
+        let equirect_shader =
+            load_shader("equirect_to_cube.wgsl", &[], EquirectToCube::check_shader);
+        let ibl_shader = load_shader("ibl.wgsl", &[], Environment::check_shader);
+        let sky_to_cube_shader = load_shader("sky_to_cube.wgsl", &[], SkyCube::check_shader);
+
+        let watcher = AssetWatcher::new()
+            .map_err(|e| eprintln!("Asset hot reloading disabled: {e}"))
+            .ok();
+
+        let mipmaps = MipmapGenerator::new(gfx, &shaders[mipmap_shader].reflection);
+        let equirect = &shaders[equirect_shader];
+        let equirect_to_cube = EquirectToCube::new(gfx, &equirect.module, &equirect.reflection);
+        let ibl = &shaders[ibl_shader];
+        let environment = Environment::new(gfx, &ibl.module, &ibl.reflection);
+        let sky_to_cube = &shaders[sky_to_cube_shader];
+        let sky_cube = SkyCube::new(gfx, &sky_to_cube.module, &sky_to_cube.reflection);
+
+        let mut assets = Self {
+            textures: SlotMap::new(),
+            bricks_texture: TextureHandle::default(),
+            crate_texture: TextureHandle::default(),
+            skybox_texture: TextureHandle::default(),
+            environment,
+            sky_cube,
+            environment_sky: None,
+            mipmaps,
+            equirect_to_cube,
             shaders,
+            shader_sources,
+            watcher,
             color_shader,
             textured_shader,
+            textured_lit_shader,
+            textured_skinned_shader,
             postprocess_shader,
             skybox_shader,
+            sky_shader,
+            mipmap_shader,
+            equirect_shader,
+            ibl_shader,
+            sky_to_cube_shader,
             meshes,
             box_mesh,
             quad_mesh,
             materials: SlotMap::new(),
+        };
+
+        // Textures are loaded once the passes generating their mip levels are ready
+        let (skybox_tex, bricks_tex, crate_tex) = pollster::block_on(async {
+            (
+                Texture::new_cube_from_file("skybox_bgra.dds", gfx, &assets)
+                    .await
+                    .unwrap(),
+                Texture::new_2d_from_file(
+                    "bricks.png",
+                    SamplerOptions {
+                        address_mode: wgpu::AddressMode::Repeat,
+                        anisotropy: 16,
+                        ..Default::default()
+                    },
+                    gfx,
+                    &assets,
+                )
+                .await
+                .unwrap(),
+                Texture::new_2d_from_file(
+                    "crate.png",
+                    SamplerOptions {
+                        anisotropy: 16,
+                        ..Default::default()
+                    },
+                    gfx,
+                    &assets,
+                )
+                .await
+                .unwrap(),
+            )
+        });
+        assets.bricks_texture = assets.textures.insert(bricks_tex);
+        assets.skybox_texture = assets.textures.insert(skybox_tex);
+        assets.crate_texture = assets.textures.insert(crate_tex);
+        assets.render_environment(gfx);
+
+        // Checked before materials are created with them, which would fail validation
+        let skybox = &assets.textures[assets.skybox_texture];
+        SkyboxMaterial::check_texture(gfx, &assets, skybox)
+            .and_then(|_| PostProcessMaterial::check_skybox_texture(gfx, &assets, skybox))
+            .context("Invalid skybox texture")
+            .unwrap();
+        for texture in [assets.bricks_texture, assets.crate_texture] {
+            TexturedMaterial::check_texture(gfx, &assets, &assets.textures[texture]).unwrap();
         }
+
+        assets
     }
 
     pub fn mesh(&self, handle: MeshHandle) -> &Mesh {
         self.meshes.get(handle).unwrap()
     }
 
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshHandle {
+        self.meshes.insert(mesh)
+    }
+
+    pub fn mesh_handles(&self) -> impl Iterator<Item = MeshHandle> + '_ {
+        self.meshes.keys()
+    }
+
+    pub fn add_texture(&mut self, texture: Texture) -> TextureHandle {
+        self.textures.insert(texture)
+    }
+
     pub fn shader(&self, handle: ShaderHandle) -> &wgpu::ShaderModule {
-        self.shaders.get(handle).unwrap()
+        &self.shaders.get(handle).unwrap().module
+    }
+
+    pub fn shader_reflection(&self, handle: ShaderHandle) -> &ShaderReflection {
+        &self.shaders.get(handle).unwrap().reflection
+    }
+
+    // Fills all mip levels of the texture from the first one
+    pub fn generate_mipmaps(&self, gfx: &Graphics, texture: &wgpu::Texture) {
+        self.mipmaps
+            .generate(gfx, gfx.queue(), self.shader(self.mipmap_shader), texture);
+    }
+
+    // Renders the panorama into the first mip level of a new float cubemap
+    pub fn equirect_to_cube(
+        &self,
+        gfx: &Graphics,
+        panorama: &wgpu::Texture,
+        face_size: u32,
+    ) -> wgpu::Texture {
+        self.equirect_to_cube
+            .convert(gfx, gfx.queue(), panorama, face_size)
+    }
+
+    // Lights lit materials with the procedural sky, or the skybox if there are no parameters.
+    // Rendering the environment takes a while, so the sky is only rendered again once it
+    // changed noticeably.
+    pub fn update_environment(&mut self, gfx: &Graphics, sky: Option<&SkyParams>) {
+        let changed = match (&self.environment_sky, sky) {
+            (None, None) => false,
+            (Some(old), Some(new)) => SkyCube::needs_render(old, new),
+            _ => true,
+        };
+        if changed {
+            self.environment_sky = sky.copied();
+            self.render_environment(gfx);
+        }
+    }
+
+    fn render_environment(&mut self, gfx: &Graphics) {
+        match &self.environment_sky {
+            Some(params) => {
+                self.sky_cube.render(gfx, params);
+                self.generate_mipmaps(gfx, self.sky_cube.texture());
+                self.environment.render(gfx, self.sky_cube.view());
+            }
+            None => {
+                let skybox = &self.textures[self.skybox_texture];
+                self.environment.render(gfx, skybox.view());
+            }
+        }
+    }
+
+    // Recompiles shaders whose files have changed and rebuilds pipelines of materials using them.
+    // Shaders that fail to compile are reported and keep their previous version.
+    pub fn reload_changed_shaders(&mut self, gfx: &Graphics) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+
+        let changed_files = watcher.changed_files();
+        let changed_shaders = self
+            .shader_sources
+            .iter()
+            .filter(|(_, src)| src.files.iter().any(|f| changed_files.contains(f)))
+            .map(|(h, _)| h)
+            .collect::<Vec<_>>();
+
+        for handle in changed_shaders {
+            let src = &self.shader_sources[handle];
+            let name = format!("{} {:?}", src.file, src.defines);
+            match self.reload_shader(gfx, handle) {
+                Result::Ok(()) => println!("Reloaded shader {name}"),
+                Err(e) => eprintln!("Failed to reload shader {name}: {e:#}"),
+            }
+        }
+    }
+
+    fn reload_shader(&mut self, gfx: &Graphics, handle: ShaderHandle) -> Result<()> {
+        let src = &self.shader_sources[handle];
+        let defines = src.defines.iter().map(String::as_str).collect::<Vec<_>>();
+        let (shader, files) = pollster::block_on(new_shader(gfx, &src.file, &defines))?;
+        // Materials keep their bind groups, so the new version must accept them
+        self.shaders[handle]
+            .reflection
+            .check_same_layout(&shader.reflection)?;
+
+        gfx.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipelines = self
+            .materials
+            .iter()
+            .filter(|(_, m)| m.shader() == handle)
+            .map(|(h, m)| (h, m.rebuild_pipeline(gfx, &shader.module)))
+            .collect::<Vec<_>>();
+        let mipmap_pipelines = (handle == self.mipmap_shader)
+            .then(|| self.mipmaps.rebuild_pipelines(gfx, &shader.module));
+        let equirect_pipeline = (handle == self.equirect_shader)
+            .then(|| self.equirect_to_cube.rebuild_pipeline(gfx, &shader.module));
+        let environment_pipelines = (handle == self.ibl_shader)
+            .then(|| self.environment.rebuild_pipelines(gfx, &shader.module));
+        let sky_cube_pipeline = (handle == self.sky_to_cube_shader)
+            .then(|| self.sky_cube.rebuild_pipeline(gfx, &shader.module));
+        if let Some(e) = pollster::block_on(gfx.pop_error_scope()) {
+            bail!("{e}");
+        }
+
+        for (material, pipeline) in pipelines {
+            self.materials[material].set_pipeline(pipeline);
+        }
+        // Textures loaded before keep their mip levels and cubemap faces
+        if let Some(pipelines) = mipmap_pipelines {
+            self.mipmaps.set_pipelines(pipelines);
+        }
+        if let Some(pipeline) = equirect_pipeline {
+            self.equirect_to_cube.set_pipeline(pipeline);
+        }
+        if let Some(pipelines) = environment_pipelines {
+            self.environment.set_pipelines(pipelines);
+            self.environment.render_brdf_lut(gfx);
+            self.render_environment(gfx);
+        }
+        if let Some(pipeline) = sky_cube_pipeline {
+            self.sky_cube.set_pipeline(pipeline);
+            self.render_environment(gfx);
+        }
+        self.shaders[handle] = shader;
+        // Includes might have changed
+        self.shader_sources[handle].files = files;
+
+        Ok(())
     }
 
     pub fn add_color_material(&mut self, gfx: &Graphics) -> MaterialHandle {
@@ -115,29 +392,51 @@ impl Assets {
         )))
     }
 
+    pub fn add_sky_material(&mut self, gfx: &Graphics) -> MaterialHandle {
+        self.materials
+            .insert(Material::Sky(SkyMaterial::new(gfx, self)))
+    }
+
     pub fn add_textured_material(
         &mut self,
         gfx: &Graphics,
         texture: TextureHandle,
+        lit: bool,
     ) -> MaterialHandle {
         self.materials
             .insert(Material::Textured(TexturedMaterial::new(
                 gfx,
                 self,
                 &self.textures[texture],
+                lit,
+            )))
+    }
+
+    pub fn add_skinned_material(
+        &mut self,
+        gfx: &Graphics,
+        texture: TextureHandle,
+    ) -> MaterialHandle {
+        self.materials
+            .insert(Material::Textured(TexturedMaterial::new_skinned(
+                gfx,
+                self,
+                &self.textures[texture],
             )))
     }
 
+    // Fog towards the horizon is taken from the skybox texture when the procedural sky is off
     pub fn add_postprocess_material(
         &mut self,
         gfx: &Graphics,
-        src_texture: &Texture,
+        source: &RenderTarget,
     ) -> MaterialHandle {
         self.materials
             .insert(Material::PostProcess(PostProcessMaterial::new(
                 gfx,
                 self,
-                src_texture,
+                source,
+                &self.textures[self.skybox_texture],
             )))
     }
 
@@ -149,15 +448,32 @@ impl Assets {
         &self.materials[handle]
     }
 
+    pub fn material_handles(&self) -> impl Iterator<Item = MaterialHandle> + '_ {
+        self.materials.keys()
+    }
+
     pub fn material_mut(&mut self, handle: MaterialHandle) -> &mut Material {
         &mut self.materials[handle]
     }
 }
 
-async fn new_shader_module(device: &wgpu::Device, src_file_path: &str) -> wgpu::ShaderModule {
-    let src = file::read_string_asset(src_file_path).await.unwrap();
-    device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(src.into()),
-    })
+// Returns the shader and all files it was built from
+async fn new_shader(
+    device: &wgpu::Device,
+    src_file_path: &str,
+    defines: &[&str],
+) -> Result<(Shader, Vec<String>)> {
+    let src = shader_preprocessor::preprocess(src_file_path, defines).await?;
+    let (naga_module, reflection) = ShaderReflection::from_wgsl(&src.source, src_file_path)?;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(src_file_path),
+        source: wgpu::ShaderSource::Naga(Cow::Owned(naga_module)),
+    });
+    if let Some(e) = device.pop_error_scope().await {
+        bail!("{e}");
+    }
+
+    Ok((Shader { module, reflection }, src.files))
 }
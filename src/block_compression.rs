@@ -0,0 +1,82 @@
+use anyhow::*;
+
+use crate::texture::TextureData;
+
+// Decodes a block into 4x4 pixels, rows tightly packed
+type DecodeBlock = fn(&[u8], &mut [u8]);
+
+// Decodes BC1-BC7 compressed textures for devices without `TEXTURE_COMPRESSION_BC`.
+// Returns uncompressed RGBA8, R8, RG8 or RGBA16F data with the same layers and mip levels.
+pub fn decompress(texture: &TextureData) -> Result<TextureData> {
+    use wgpu::TextureFormat as F;
+
+    let (format, decode_block): (F, DecodeBlock) = match texture.format {
+        F::Bc1RgbaUnorm => (F::Rgba8Unorm, |b, out| bcdec_rs::bc1(b, out, 16)),
+        F::Bc1RgbaUnormSrgb => (F::Rgba8UnormSrgb, |b, out| bcdec_rs::bc1(b, out, 16)),
+        F::Bc2RgbaUnorm => (F::Rgba8Unorm, |b, out| bcdec_rs::bc2(b, out, 16)),
+        F::Bc2RgbaUnormSrgb => (F::Rgba8UnormSrgb, |b, out| bcdec_rs::bc2(b, out, 16)),
+        F::Bc3RgbaUnorm => (F::Rgba8Unorm, |b, out| bcdec_rs::bc3(b, out, 16)),
+        F::Bc3RgbaUnormSrgb => (F::Rgba8UnormSrgb, |b, out| bcdec_rs::bc3(b, out, 16)),
+        F::Bc4RUnorm => (F::R8Unorm, |b, out| bcdec_rs::bc4(b, out, 4, false)),
+        F::Bc4RSnorm => (F::R8Snorm, |b, out| bcdec_rs::bc4(b, out, 4, true)),
+        F::Bc5RgUnorm => (F::Rg8Unorm, |b, out| bcdec_rs::bc5(b, out, 8, false)),
+        F::Bc5RgSnorm => (F::Rg8Snorm, |b, out| bcdec_rs::bc5(b, out, 8, true)),
+        F::Bc6hRgbUfloat => (F::Rgba16Float, |b, out| decode_bc6h(b, out, false)),
+        F::Bc6hRgbFloat => (F::Rgba16Float, |b, out| decode_bc6h(b, out, true)),
+        F::Bc7RgbaUnorm => (F::Rgba8Unorm, |b, out| bcdec_rs::bc7(b, out, 16)),
+        F::Bc7RgbaUnormSrgb => (F::Rgba8UnormSrgb, |b, out| bcdec_rs::bc7(b, out, 16)),
+        f => bail!("{f:?} is not a BC format"),
+    };
+
+    let block_len = texture.format.block_copy_size(None).unwrap() as usize;
+    let pixel_len = format.block_copy_size(None).unwrap() as usize;
+
+    let mut blocks = texture.data.chunks_exact(block_len);
+    let mut data = Vec::with_capacity(texture.expected_data_len() * pixel_len * 16 / block_len);
+    let mut decoded = [0u8; 4 * 4 * 8];
+
+    for _ in 0..texture.layer_count {
+        for mip in 0..texture.mip_level_count {
+            let width = (texture.width >> mip).max(1) as usize;
+            let height = (texture.height >> mip).max(1) as usize;
+            let mip_start = data.len();
+            data.resize(mip_start + width * height * pixel_len, 0);
+
+            for block_y in 0..height.div_ceil(4) {
+                for block_x in 0..width.div_ceil(4) {
+                    let block = blocks
+                        .next()
+                        .ok_or_else(|| anyhow!("Truncated block compressed data"))?;
+                    decode_block(block, &mut decoded);
+
+                    // Blocks at the edges of textures with sizes not divisible by 4 are cropped
+                    let x = block_x * 4;
+                    let row_len = (width - x).min(4) * pixel_len;
+                    for y in block_y * 4..(block_y * 4 + 4).min(height) {
+                        let src = (y % 4) * 4 * pixel_len;
+                        let dst = mip_start + (y * width + x) * pixel_len;
+                        data[dst..dst + row_len].copy_from_slice(&decoded[src..src + row_len]);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(TextureData {
+        format,
+        data,
+        ..*texture
+    })
+}
+
+// Outputs half floats with alpha set to 1
+fn decode_bc6h(block: &[u8], out: &mut [u8], signed: bool) {
+    const HALF_ONE: u16 = 0x3c00;
+
+    let mut rgb = [0u16; 4 * 4 * 3];
+    bcdec_rs::bc6h_half(block, &mut rgb, 4 * 3, signed);
+    for (px, rgb) in out.chunks_exact_mut(8).zip(rgb.chunks_exact(3)) {
+        let rgba = [rgb[0], rgb[1], rgb[2], HALF_ONE];
+        px.copy_from_slice(bytemuck::cast_slice(&rgba));
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::graphics::Graphics;
+
+// Copy of a texture on its way from the GPU into CPU memory
+pub struct TextureReadback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+    mapped: mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl TextureReadback {
+    // Schedules copying of the first mip level of a 2D texture. The texture must have
+    // `COPY_SRC` usage and a 4-byte RGBA or BGRA format.
+    pub fn new(gfx: &Graphics, texture: &wgpu::Texture) -> Result<Self> {
+        let format = texture.format();
+        if !matches!(
+            format,
+            wgpu::TextureFormat::Rgba8Unorm
+                | wgpu::TextureFormat::Rgba8UnormSrgb
+                | wgpu::TextureFormat::Bgra8Unorm
+                | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            bail!("Unsupported format for readback: {format:?}");
+        }
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            bail!("Texture lacks COPY_SRC usage");
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        // Rows in the destination buffer must be aligned
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = gfx.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder =
+            gfx.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        gfx.queue().submit(Some(encoder.finish()));
+
+        let (tx, rx) = mpsc::channel();
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+
+        Ok(Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            format,
+            mapped: rx,
+        })
+    }
+
+    // Returns the image if the copy has finished
+    pub fn try_finish(&self, gfx: &Graphics) -> Option<Result<image::RgbaImage>> {
+        gfx.poll(wgpu::Maintain::Poll);
+        match self.mapped.try_recv() {
+            Ok(r) => Some(self.read(r)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(anyhow!("Readback was cancelled"))),
+        }
+    }
+
+    // Blocks until the copy has finished
+    pub fn wait(self, gfx: &Graphics) -> Result<image::RgbaImage> {
+        gfx.poll(wgpu::Maintain::Wait);
+        let mapped = self.mapped.recv()?;
+        self.read(mapped)
+    }
+
+    fn read(&self, mapped: Result<(), wgpu::BufferAsyncError>) -> Result<image::RgbaImage> {
+        mapped?;
+
+        let row_len = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_len * self.height as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_len]);
+            }
+        }
+        self.buffer.unmap();
+
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for px in pixels.chunks_exact_mut(4) {
+                px.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .ok_or_else(|| anyhow!("Invalid readback size"))
+    }
+}
+
+// Captured frames waiting for their readbacks to finish before being saved
+#[derive(Default)]
+pub struct Screenshots {
+    pending: Vec<TextureReadback>,
+}
+
+impl Screenshots {
+    pub fn capture(&mut self, gfx: &Graphics, texture: &wgpu::Texture) {
+        match TextureReadback::new(gfx, texture) {
+            Ok(r) => self.pending.push(r),
+            Err(e) => eprintln!("Failed to capture screenshot: {e}"),
+        }
+    }
+
+    // Saves finished screenshots as PNGs into the working directory
+    pub fn update(&mut self, gfx: &Graphics) {
+        self.pending.retain(|readback| {
+            let Some(image) = readback.try_finish(gfx) else {
+                return true;
+            };
+
+            // Encoding is slow enough to cause a hitch, so doing it in background
+            std::thread::spawn(move || {
+                let path = timestamped_path("screenshot", "png");
+                match image.and_then(|i| Ok(i.save(&path)?)) {
+                    Ok(()) => println!("Saved {}", path.display()),
+                    Err(e) => eprintln!("Failed to save screenshot: {e}"),
+                }
+            });
+            false
+        });
+    }
+}
+
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("{prefix}-{ms}.{extension}"))
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use hecs::{Entity, World};
+
+use crate::animation::{AnimationClip, Pose, PoseBlend};
+
+use super::{Parent, Transform};
+
+// Plays the clips of a spawned model by moving the entities of its nodes. All clips are
+// sampled every frame and blended by weight, together with the rest pose while their weights
+// add up to less than 1.
+pub struct AnimationPlayer {
+    pub layers: Vec<AnimationLayer>,
+    // Entity spawned for each node of the model
+    targets: Vec<Entity>,
+    rest_poses: Vec<Pose>,
+}
+
+// Playback state of a clip
+pub struct AnimationLayer {
+    pub clip: Arc<AnimationClip>,
+    pub time: f32,
+    pub speed: f32,
+    pub weight: f32,
+    pub looping: bool,
+}
+
+impl AnimationPlayer {
+    // Nothing plays until a layer is given weight. `targets` and `rest_poses` are indexed by
+    // node.
+    pub fn new(clips: &[Arc<AnimationClip>], targets: Vec<Entity>, rest_poses: Vec<Pose>) -> Self {
+        let layers = clips
+            .iter()
+            .map(|clip| AnimationLayer {
+                clip: clip.clone(),
+                time: 0.0,
+                speed: 1.0,
+                weight: 0.0,
+                looping: true,
+            })
+            .collect();
+        Self {
+            layers,
+            targets,
+            rest_poses,
+        }
+    }
+
+    // Plays the clip from the start on its own
+    pub fn play(&mut self, name: &str) {
+        for layer in &mut self.layers {
+            if layer.clip.name == name {
+                layer.time = 0.0;
+                layer.weight = 1.0;
+            } else {
+                layer.weight = 0.0;
+            }
+        }
+    }
+
+    // Sets the local transforms of the animated nodes, before parents are updated
+    pub fn update(dt: f32, world: &mut World) {
+        let mut poses = Vec::new();
+        for (_, player) in world.query_mut::<&mut AnimationPlayer>() {
+            player.advance(dt);
+            poses.extend(player.sample());
+        }
+
+        for (e, pose) in poses {
+            let transform = pose.to_transform();
+            if let Ok(mut parent) = world.get::<&mut Parent>(e) {
+                parent.local = transform;
+            } else if let Ok(mut t) = world.get::<&mut Transform>(e) {
+                *t = transform;
+            }
+        }
+    }
+
+    fn advance(&mut self, dt: f32) {
+        for layer in &mut self.layers {
+            let duration = layer.clip.duration;
+            layer.time += dt * layer.speed;
+            layer.time = if layer.looping && duration > 0.0 {
+                layer.time.rem_euclid(duration)
+            } else {
+                layer.time.clamp(0.0, duration)
+            };
+        }
+    }
+
+    // Nodes animated by any clip, even without weight, so that they return to the rest pose
+    fn sample(&self) -> Vec<(Entity, Pose)> {
+        let mut blends = Vec::new();
+        blends.resize_with(self.targets.len(), || None);
+        for layer in &self.layers {
+            for channel in &layer.clip.channels {
+                if let Some(blend) = blends.get_mut(channel.node) {
+                    blend.get_or_insert_with(PoseBlend::default).add(
+                        channel.property,
+                        channel.sample(layer.time),
+                        layer.weight.max(0.0),
+                    );
+                }
+            }
+        }
+
+        blends
+            .into_iter()
+            .enumerate()
+            .filter_map(|(node, blend)| {
+                let pose = blend?.finish(&self.rest_poses[node]);
+                Some((self.targets[node], pose))
+            })
+            .collect()
+    }
+}
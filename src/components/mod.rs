@@ -1,23 +1,33 @@
+pub use animation_player::AnimationPlayer;
 pub use camera::Camera;
 pub use grab::Grab;
+pub use parent::Parent;
 pub use player::Player;
 pub use player_target::PlayerTarget;
-pub use rigid_body::{RigidBody, RigidBodyParams};
+pub use rigid_body::{PendingRigidBody, RigidBody, RigidBodyParams};
+pub use skin::Skin;
 pub use transform::Transform;
+pub use tween::{Tween, TweenMode};
 
 use crate::assets::{MaterialHandle, MeshHandle};
 
+mod animation_player;
 mod camera;
 mod grab;
+mod parent;
 mod player;
 mod player_target;
 mod rigid_body;
+mod skin;
 mod transform;
+mod tween;
 
 pub struct RenderTags(pub u32);
 pub struct RenderOrder(pub i32);
 pub struct Mesh(pub MeshHandle);
 pub struct Material(pub MaterialHandle);
+// Shown in the inspector
+pub struct Name(pub String);
 
 pub const RENDER_TAG_SCENE: u32 = 0b00000001;
 pub const RENDER_TAG_POST_PROCESS: u32 = 0b00000010;
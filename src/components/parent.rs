@@ -0,0 +1,67 @@
+use std::collections::{HashMap, HashSet};
+
+use hecs::{Entity, World};
+
+use super::Transform;
+
+// Places the entity relative to another one. Its `Transform` is derived from the parent's on
+// every update, so `local` is the one to change. Entities in a cycle of parents are left where
+// they are.
+pub struct Parent {
+    pub entity: Entity,
+    pub local: Transform,
+}
+
+impl Parent {
+    // Updates the transforms of all children, parents first
+    pub fn update(world: &mut World) {
+        let parents = world
+            .query_mut::<&Parent>()
+            .into_iter()
+            .map(|(e, p)| (e, (p.entity, p.local)))
+            .collect::<HashMap<_, _>>();
+
+        let mut transforms = HashMap::new();
+        let mut visiting = HashSet::new();
+        for &e in parents.keys() {
+            world_transform(e, world, &parents, &mut transforms, &mut visiting);
+        }
+
+        for (e, transform) in transforms {
+            if parents.contains_key(&e) {
+                if let Ok(mut t) = world.get::<&mut Transform>(e) {
+                    *t = transform;
+                }
+            }
+        }
+    }
+}
+
+// None if the entity or one of its ancestors has no transform, or if it is its own ancestor.
+// `visiting` holds the entities whose transform is being computed further up the stack.
+fn world_transform(
+    e: Entity,
+    world: &World,
+    parents: &HashMap<Entity, (Entity, Transform)>,
+    transforms: &mut HashMap<Entity, Transform>,
+    visiting: &mut HashSet<Entity>,
+) -> Option<Transform> {
+    if let Some(&t) = transforms.get(&e) {
+        return Some(t);
+    }
+    if !visiting.insert(e) {
+        return None;
+    }
+
+    let t = match parents.get(&e) {
+        Some(&(parent, local)) => {
+            world_transform(parent, world, parents, transforms, visiting).map(|t| t.child(&local))
+        }
+        None => world.get::<&Transform>(e).ok().map(|t| *t),
+    };
+    visiting.remove(&e);
+    if let Some(t) = t {
+        transforms.insert(e, t);
+    }
+    t
+}
@@ -1,13 +1,26 @@
+use std::thread::JoinHandle;
+
+use anyhow::{anyhow, bail};
+use hecs::World;
 use rapier3d::prelude::*;
 
-use crate::math::Vec3;
+use crate::math::{to_point3, UnitQuat, Vec3};
+use crate::mesh::Mesh;
 use crate::physics::Physics;
+use crate::primitives::Primitive;
+use crate::terrain::Heightmap;
+
+use super::Transform;
 
 pub struct RigidBody {
     handle: RigidBodyHandle,
-    movable: bool,
+    // Type to restore after temporarily making the body kinematic
+    body_type: RigidBodyType,
+    // Whether the collider is sized by the scale of the transform, as boxes are
+    scaled_collider: bool,
 }
 
+#[derive(Clone, Copy)]
 pub struct RigidBodyParams {
     pub pos: Vec3,
     pub scale: Vec3,
@@ -22,7 +35,8 @@ impl RigidBody {
             movable,
         } = params;
 
-        let body = RigidBodyBuilder::new(body_type(movable))
+        let body_type = body_type(movable);
+        let body = RigidBodyBuilder::new(body_type)
             .translation(vector![pos.x, pos.y, pos.z])
             .build();
         let collider = ColliderBuilder::cuboid(scale.x, scale.y, scale.z)
@@ -32,7 +46,120 @@ impl RigidBody {
 
         let handle = physics.add_body(body, collider);
 
-        Self { handle, movable }
+        Self {
+            handle,
+            body_type,
+            scaled_collider: true,
+        }
+    }
+
+    // Collider matching the mesh generated for the primitive, which is built at its final size
+    // and so takes no scale
+    pub fn primitive(
+        primitive: &Primitive,
+        pos: Vec3,
+        movable: bool,
+        physics: &mut Physics,
+    ) -> Self {
+        let params = RigidBodyParams {
+            pos,
+            scale: Vec3::from_element(1.0),
+            movable,
+        };
+        Self::with_shape(primitive.shape(), params, physics)
+    }
+
+    // Smallest convex shape around the mesh, which must keep its data. Scale is applied to the
+    // mesh, as to the entity.
+    pub fn convex_hull(
+        mesh: &Mesh,
+        params: RigidBodyParams,
+        physics: &mut Physics,
+    ) -> anyhow::Result<Self> {
+        let (points, _) = mesh_geometry(mesh, params.scale)?;
+        let shape = SharedShape::convex_hull(&points)
+            .ok_or_else(|| anyhow!("Failed to compute the convex hull, the mesh may be flat"))?;
+        Ok(Self::with_shape(shape, params, physics))
+    }
+
+    // Concave mesh approximated by several convex parts with V-HACD. That is slow for detailed
+    // meshes, so the parts are computed on a separate thread and the body is added to the entity
+    // with the returned component once they are ready.
+    pub fn convex_decomposition(
+        mesh: &Mesh,
+        params: RigidBodyParams,
+    ) -> anyhow::Result<PendingRigidBody> {
+        let (points, triangles) = mesh_geometry(mesh, params.scale)?;
+        let shape =
+            std::thread::spawn(move || SharedShape::convex_decomposition(&points, &triangles));
+        Ok(PendingRigidBody { shape, params })
+    }
+
+    // Exact triangles of the mesh. Triangle meshes have no volume to compute a mass from, so the
+    // body is always fixed.
+    pub fn trimesh(
+        mesh: &Mesh,
+        pos: Vec3,
+        scale: Vec3,
+        physics: &mut Physics,
+    ) -> anyhow::Result<Self> {
+        let (points, triangles) = mesh_geometry(mesh, scale)?;
+        let params = RigidBodyParams {
+            pos,
+            scale,
+            movable: false,
+        };
+        Ok(Self::with_shape(
+            SharedShape::trimesh(points, triangles),
+            params,
+            physics,
+        ))
+    }
+
+    // The shape is already scaled
+    fn with_shape(shape: SharedShape, params: RigidBodyParams, physics: &mut Physics) -> Self {
+        let RigidBodyParams { pos, movable, .. } = params;
+        let body_type = body_type(movable);
+        let body = RigidBodyBuilder::new(body_type)
+            .translation(vector![pos.x, pos.y, pos.z])
+            .build();
+        let collider = ColliderBuilder::new(shape)
+            .restitution(0.2)
+            .friction(0.7)
+            .build();
+
+        let handle = physics.add_body(body, collider);
+
+        Self {
+            handle,
+            body_type,
+            scaled_collider: false,
+        }
+    }
+
+    // Fixed terrain centered on `pos` horizontally, see `Heightmap::chunks` for the matching mesh
+    pub fn heightfield(
+        heightmap: &Heightmap,
+        pos: Vec3,
+        size: Vec3,
+        physics: &mut Physics,
+    ) -> Self {
+        let body_type = RigidBodyType::Fixed;
+        let body = RigidBodyBuilder::new(body_type)
+            .translation(vector![pos.x, pos.y, pos.z])
+            .build();
+        let collider =
+            ColliderBuilder::heightfield(heightmap.to_matrix(size), vector![size.x, 1.0, size.z])
+                .friction(0.7)
+                .build();
+
+        let handle = physics.add_body(body, collider);
+
+        Self {
+            handle,
+            body_type,
+            scaled_collider: false,
+        }
     }
 
     pub fn handle(&self) -> RigidBodyHandle {
@@ -44,10 +171,123 @@ impl RigidBody {
         let new_type = if kinematic {
             RigidBodyType::KinematicPositionBased
         } else {
-            body_type(self.movable)
+            self.body_type
         };
         body.set_body_type(new_type, true);
     }
+
+    pub fn body_type(&self) -> RigidBodyType {
+        self.body_type
+    }
+
+    pub fn set_body_type(&mut self, physics: &mut Physics, body_type: RigidBodyType) {
+        self.body_type = body_type;
+        physics
+            .bodies
+            .get_mut(self.handle)
+            .unwrap()
+            .set_body_type(body_type, true);
+    }
+
+    // Moves the body to match the given pose. Rotation is expected in the `Transform` convention,
+    // which is inverse to Rapier's. Scale is applied to boxes only, other colliders keep the size
+    // they were built with.
+    pub fn set_pose(&self, physics: &mut Physics, pos: Vec3, rot: UnitQuat, scale: Vec3) {
+        let body = physics.bodies.get_mut(self.handle).unwrap();
+        body.set_translation(pos, true);
+        body.set_rotation(rot.inverse(), true);
+        self.set_collider_scale(physics, scale);
+    }
+
+    // Like `set_pose`, but kinematic bodies travel to the pose during the next physics step of
+    // `dt`, pushing dynamic bodies on their way instead of passing through them
+    pub fn move_to(&self, physics: &mut Physics, dt: f32, pos: Vec3, rot: UnitQuat, scale: Vec3) {
+        let body = physics.bodies.get_mut(self.handle).unwrap();
+        match body.body_type() {
+            RigidBodyType::KinematicPositionBased => {
+                body.set_next_kinematic_position(Isometry::from_parts(pos.into(), rot.inverse()));
+            }
+            RigidBodyType::KinematicVelocityBased if dt > 0.0 => {
+                let linvel = (pos - body.translation()) / dt;
+                let angvel = (rot.inverse() * body.rotation().inverse()).scaled_axis() / dt;
+                body.set_linvel(linvel, true);
+                body.set_angvel(angvel, true);
+            }
+            _ => return self.set_pose(physics, pos, rot, scale),
+        }
+        self.set_collider_scale(physics, scale);
+    }
+
+    fn set_collider_scale(&self, physics: &mut Physics, scale: Vec3) {
+        if !self.scaled_collider {
+            return;
+        }
+
+        let body = physics.bodies.get(self.handle).unwrap();
+        for &collider in body.colliders() {
+            let collider = physics.colliders.get_mut(collider).unwrap();
+            let resized = collider
+                .shape()
+                .as_cuboid()
+                .is_some_and(|c| c.half_extents != scale);
+            if resized {
+                collider.set_shape(SharedShape::cuboid(scale.x, scale.y, scale.z));
+            }
+        }
+    }
+}
+
+// Rigid body whose collider is still being computed, replaced by the body once it is ready
+pub struct PendingRigidBody {
+    shape: JoinHandle<SharedShape>,
+    params: RigidBodyParams,
+}
+
+impl PendingRigidBody {
+    pub fn update(world: &mut World, physics: &mut Physics) {
+        let finished: Vec<_> = world
+            .query_mut::<&PendingRigidBody>()
+            .into_iter()
+            .filter(|(_, pending)| pending.shape.is_finished())
+            .map(|(e, _)| e)
+            .collect();
+
+        for e in finished {
+            let pending = world.remove_one::<PendingRigidBody>(e).unwrap();
+            let Ok(shape) = pending.shape.join() else {
+                eprintln!("Failed to compute the convex decomposition");
+                continue;
+            };
+            // Placed where the entity is now, it may have been moved since
+            let body = RigidBody::with_shape(shape, pending.params, physics);
+            let transform = world.get::<&Transform>(e).unwrap();
+            body.set_pose(
+                physics,
+                transform.position(),
+                transform.rotation(),
+                transform.scale(),
+            );
+            drop(transform);
+            world.insert_one(e, body).unwrap();
+        }
+    }
+}
+
+type MeshGeometry = (Vec<Point<Real>>, Vec<[u32; 3]>);
+
+fn mesh_geometry(mesh: &Mesh, scale: Vec3) -> anyhow::Result<MeshGeometry> {
+    let Some(data) = mesh.data() else {
+        bail!("The mesh doesn't keep its data, it must be created with `keep_data`");
+    };
+    if data.triangles.is_empty() {
+        bail!("The mesh has no triangles");
+    }
+    let points = data
+        .positions
+        .iter()
+        .map(|p| to_point3(p.component_mul(&scale)))
+        .collect();
+    Ok((points, data.triangles.clone()))
 }
 
 fn body_type(movable: bool) -> RigidBodyType {
@@ -0,0 +1,42 @@
+use hecs::{Entity, World};
+
+use crate::assets::Assets;
+use crate::materials;
+use crate::math::Mat4;
+
+use super::{Material, Transform};
+
+// Deforms the mesh of the entity by the transforms of the joint entities. Needs a skinned
+// material, see `TexturedMaterial::new_skinned`.
+pub struct Skin {
+    pub joints: Vec<Entity>,
+    // Bring vertices from mesh space to the space of each joint in the bind pose
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    // Passes joint matrices relative to the entity to its material, after transforms are updated
+    pub fn update(world: &World, assets: &mut Assets) {
+        for (_, (skin, transform, material)) in
+            world.query::<(&Skin, &Transform, &Material)>().iter()
+        {
+            let Some(inverse) = transform.matrix().try_inverse() else {
+                continue;
+            };
+            let joints = skin
+                .joints
+                .iter()
+                .zip(&skin.inverse_bind_matrices)
+                .map(|(&joint, inverse_bind)| {
+                    let joint = world
+                        .get::<&Transform>(joint)
+                        .map_or(Mat4::identity(), |t| t.matrix());
+                    inverse * joint * inverse_bind
+                })
+                .collect();
+            if let materials::Material::Textured(m) = assets.material_mut(material.0) {
+                m.set_joint_matrices(joints);
+            }
+        }
+    }
+}
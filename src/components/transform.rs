@@ -15,7 +15,6 @@ pub struct Transform {
     rot: UnitQuat,
 }
 
-// TODO Transform hierarchies
 impl Transform {
     pub fn new(pos: Vec3, scale: Vec3) -> Self {
         let m = Mat4::identity();
@@ -53,6 +52,30 @@ impl Transform {
         self.pos
     }
 
+    pub fn rotation(&self) -> UnitQuat {
+        self.rot
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    // Transform of a child placed at `local` relative to this one. Scales combine per axis, which
+    // is only exact if this scale is uniform or the child is not rotated.
+    pub fn child(&self, local: &Transform) -> Transform {
+        let pos = self.m.transform_point(&local.pos.into()).coords;
+        // Rotations are stored inverted, see `rebuild_matrix`
+        let rot = local.rot * self.rot;
+        let mut res = Self {
+            m: Mat4::identity(),
+            scale: self.scale.component_mul(&local.scale),
+            pos,
+            rot,
+        };
+        res.rebuild_matrix();
+        res
+    }
+
     pub fn look_at(&mut self, target: Vec3) {
         self.rot = UnitQuat::look_at_rh(&(target - self.pos), &Vec3::y_axis());
         self.rebuild_matrix();
@@ -68,6 +91,11 @@ impl Transform {
         self.rebuild_matrix();
     }
 
+    pub fn set_rotation(&mut self, rot: UnitQuat) {
+        self.rot = rot;
+        self.rebuild_matrix();
+    }
+
     pub fn set_scale(&mut self, scale: Vec3) {
         self.scale = scale;
         self.rebuild_matrix();
@@ -0,0 +1,119 @@
+use hecs::World;
+
+use crate::animation::{Pose, Track};
+use crate::math::{UnitQuat, Vec3};
+use crate::physics::Physics;
+
+use super::{Parent, RigidBody, Transform};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TweenMode {
+    // Stops at the last keyframe
+    Once,
+    Loop,
+    // Plays forward then backward
+    PingPong,
+}
+
+// Moves an entity along keyframed tracks, e.g. for platforms and doors. Properties without
+// keyframes keep their value. Rigid bodies follow, kinematic ones pushing dynamic bodies on
+// their way. Children move locally, their bodies are left alone.
+pub struct Tween {
+    pub translation: Track<Vec3>,
+    // As in `Pose`, i.e. not inverted like in `Transform`
+    pub rotation: Track<UnitQuat>,
+    pub scale: Track<Vec3>,
+    pub mode: TweenMode,
+    // Playback time, running up to twice the duration with ping-pong
+    pub time: f32,
+    pub speed: f32,
+}
+
+impl Default for Tween {
+    fn default() -> Self {
+        Self {
+            translation: Track::default(),
+            rotation: Track::default(),
+            scale: Track::default(),
+            mode: TweenMode::Loop,
+            time: 0.0,
+            speed: 1.0,
+        }
+    }
+}
+
+impl Tween {
+    // Time of the last keyframe of all tracks
+    pub fn duration(&self) -> f32 {
+        self.translation
+            .duration()
+            .max(self.rotation.duration())
+            .max(self.scale.duration())
+    }
+
+    // Time it takes to come back to the start
+    pub fn period(&self) -> f32 {
+        match self.mode {
+            TweenMode::PingPong => self.duration() * 2.0,
+            _ => self.duration(),
+        }
+    }
+
+    // Before the physics step, which brings kinematic bodies to their new pose
+    pub fn update(dt: f32, world: &mut World, physics: &mut Physics) {
+        for (_, (tween, transform, parent, body)) in world.query_mut::<(
+            &mut Tween,
+            &mut Transform,
+            Option<&mut Parent>,
+            Option<&RigidBody>,
+        )>() {
+            tween.advance(dt);
+
+            if let Some(parent) = parent {
+                parent.local = tween.sample(&parent.local);
+                continue;
+            }
+
+            *transform = tween.sample(transform);
+            if let Some(body) = body {
+                let (pos, rot, scale) = (
+                    transform.position(),
+                    transform.rotation(),
+                    transform.scale(),
+                );
+                body.move_to(physics, dt, pos, rot, scale);
+            }
+        }
+    }
+
+    fn advance(&mut self, dt: f32) {
+        let period = self.period();
+        self.time += dt * self.speed;
+        self.time = if self.mode != TweenMode::Once && period > 0.0 {
+            self.time.rem_euclid(period)
+        } else {
+            self.time.clamp(0.0, period)
+        };
+    }
+
+    fn sample(&self, transform: &Transform) -> Transform {
+        let duration = self.duration();
+        let time = if self.time > duration {
+            duration * 2.0 - self.time
+        } else {
+            self.time
+        };
+
+        let mut pose = Pose::from_transform(transform);
+        if let Some(translation) = self.translation.sample(time) {
+            pose.translation = translation;
+        }
+        if let Some(rotation) = self.rotation.sample(time) {
+            pose.rotation = rotation;
+        }
+        if let Some(scale) = self.scale.sample(time) {
+            pose.scale = scale;
+        }
+        pose.to_transform()
+    }
+}
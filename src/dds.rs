@@ -0,0 +1,131 @@
+use anyhow::*;
+use ddsfile::{Caps2, D3D10ResourceDimension, D3DFormat, Dds, DxgiFormat, MiscFlag};
+
+use crate::texture::TextureData;
+
+// Reads a 2D, array or cubemap DDS texture with the mip levels stored in the file
+pub fn decode(data: &[u8]) -> Result<TextureData> {
+    let dds = Dds::read(&mut std::io::Cursor::new(data)).context("Invalid DDS file")?;
+
+    let is_volume = dds.header.caps2.contains(Caps2::VOLUME)
+        || dds.get_depth() > 1
+        || dds
+            .header10
+            .as_ref()
+            .is_some_and(|h| h.resource_dimension == D3D10ResourceDimension::Texture3D);
+    if is_volume {
+        bail!("Volume textures are not supported");
+    }
+
+    let (width, height) = (dds.get_width(), dds.get_height());
+    if width == 0 || height == 0 {
+        bail!("Invalid size {width}x{height}");
+    }
+
+    let cube = match &dds.header10 {
+        Some(h) => h.misc_flag.contains(MiscFlag::TEXTURECUBE),
+        None => dds.header.caps2.contains(Caps2::CUBEMAP),
+    };
+    if cube && dds.header10.is_none() && !dds.header.caps2.contains(Caps2::CUBEMAP_ALLFACES) {
+        bail!("Cubemaps with missing faces are not supported");
+    }
+    let layer_count = match &dds.header10 {
+        // Counts cubes rather than faces
+        Some(h) => h.array_size.max(1) * if cube { 6 } else { 1 },
+        None if cube => 6,
+        None => 1,
+    };
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let max_mips = size.max_mips(wgpu::TextureDimension::D2);
+    let mip_level_count = dds.get_num_mipmap_levels().max(1);
+    if mip_level_count > max_mips {
+        bail!("{mip_level_count} mip levels specified for a {width}x{height} texture");
+    }
+
+    let format = texture_format(&dds)?;
+
+    let mut texture = TextureData {
+        width,
+        height,
+        layer_count,
+        mip_level_count,
+        cube,
+        format,
+        data: Vec::new(),
+    };
+
+    let expected_len = texture.expected_data_len();
+    if dds.data.len() < expected_len {
+        bail!(
+            "Truncated data: expected {expected_len} bytes, found {}",
+            dds.data.len()
+        );
+    }
+    texture.data = dds.data;
+    texture.data.truncate(expected_len);
+
+    Ok(texture)
+}
+
+fn texture_format(dds: &Dds) -> Result<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+
+    if let Some(format) = dds.get_dxgi_format() {
+        return Ok(match format {
+            DxgiFormat::R8G8B8A8_UNorm => F::Rgba8Unorm,
+            DxgiFormat::R8G8B8A8_UNorm_sRGB => F::Rgba8UnormSrgb,
+            DxgiFormat::B8G8R8A8_UNorm | DxgiFormat::B8G8R8X8_UNorm => F::Bgra8Unorm,
+            DxgiFormat::B8G8R8A8_UNorm_sRGB | DxgiFormat::B8G8R8X8_UNorm_sRGB => F::Bgra8UnormSrgb,
+            DxgiFormat::R8_UNorm => F::R8Unorm,
+            DxgiFormat::R8G8_UNorm => F::Rg8Unorm,
+            DxgiFormat::R10G10B10A2_UNorm => F::Rgb10a2Unorm,
+            DxgiFormat::R11G11B10_Float => F::Rg11b10Float,
+            DxgiFormat::R9G9B9E5_SharedExp => F::Rgb9e5Ufloat,
+            DxgiFormat::R16_Float => F::R16Float,
+            DxgiFormat::R16G16_Float => F::Rg16Float,
+            DxgiFormat::R16G16B16A16_Float => F::Rgba16Float,
+            // Converted to 16 bits on upload if the device can't filter 32-bit floats
+            DxgiFormat::R32_Float => F::R32Float,
+            DxgiFormat::R32G32_Float => F::Rg32Float,
+            DxgiFormat::R32G32B32A32_Float => F::Rgba32Float,
+            DxgiFormat::BC1_UNorm => F::Bc1RgbaUnorm,
+            DxgiFormat::BC1_UNorm_sRGB => F::Bc1RgbaUnormSrgb,
+            DxgiFormat::BC2_UNorm => F::Bc2RgbaUnorm,
+            DxgiFormat::BC2_UNorm_sRGB => F::Bc2RgbaUnormSrgb,
+            DxgiFormat::BC3_UNorm => F::Bc3RgbaUnorm,
+            DxgiFormat::BC3_UNorm_sRGB => F::Bc3RgbaUnormSrgb,
+            DxgiFormat::BC4_UNorm => F::Bc4RUnorm,
+            DxgiFormat::BC4_SNorm => F::Bc4RSnorm,
+            DxgiFormat::BC5_UNorm => F::Bc5RgUnorm,
+            DxgiFormat::BC5_SNorm => F::Bc5RgSnorm,
+            DxgiFormat::BC6H_UF16 => F::Bc6hRgbUfloat,
+            DxgiFormat::BC6H_SF16 => F::Bc6hRgbFloat,
+            DxgiFormat::BC7_UNorm => F::Bc7RgbaUnorm,
+            DxgiFormat::BC7_UNorm_sRGB => F::Bc7RgbaUnormSrgb,
+            _ => bail!("Unsupported format {format:?}"),
+        });
+    }
+
+    // Legacy headers don't say whether colors are sRGB, assuming they are
+    match dds.get_d3d_format() {
+        Some(D3DFormat::A8R8G8B8 | D3DFormat::X8R8G8B8) => Ok(F::Bgra8UnormSrgb),
+        Some(D3DFormat::A8B8G8R8 | D3DFormat::X8B8G8R8) => Ok(F::Rgba8UnormSrgb),
+        Some(D3DFormat::DXT1) => Ok(F::Bc1RgbaUnormSrgb),
+        Some(D3DFormat::DXT2 | D3DFormat::DXT3) => Ok(F::Bc2RgbaUnormSrgb),
+        Some(D3DFormat::DXT4 | D3DFormat::DXT5) => Ok(F::Bc3RgbaUnormSrgb),
+        Some(D3DFormat::L8) => Ok(F::R8Unorm),
+        Some(D3DFormat::R16F) => Ok(F::R16Float),
+        Some(D3DFormat::G16R16F) => Ok(F::Rg16Float),
+        Some(D3DFormat::A16B16G16R16F) => Ok(F::Rgba16Float),
+        Some(D3DFormat::R32F) => Ok(F::R32Float),
+        Some(D3DFormat::G32R32F) => Ok(F::Rg32Float),
+        Some(D3DFormat::A32B32G32R32F) => Ok(F::Rgba32Float),
+        Some(format) => bail!("Unsupported format {format:?}"),
+        None => bail!("Unknown pixel format {:?}", dds.header.spf),
+    }
+}
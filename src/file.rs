@@ -2,8 +2,12 @@ use std::path::PathBuf;
 
 use anyhow::*;
 
+pub fn assets_dir() -> PathBuf {
+    PathBuf::from("./assets")
+}
+
 fn full_path(relative_path: &str) -> PathBuf {
-    std::path::Path::new("./assets").join(relative_path)
+    assets_dir().join(relative_path)
 }
 
 pub async fn read_binary_asset(file_path: &str) -> Result<Vec<u8>> {
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use ::gltf::{buffer, image, mesh, texture, Gltf};
+use anyhow::*;
+use base64::Engine;
+
+use crate::animation::{AnimationClip, Channel, Interpolation, Property};
+use crate::assets::Assets;
+use crate::components::Transform;
+use crate::file;
+use crate::graphics::Graphics;
+use crate::materials::{TexturedMaterial, MAX_JOINTS};
+use crate::math::{linear_to_srgb, srgb_to_linear, Mat4, Quat, UnitQuat, Vec3, Vec4};
+use crate::mesh::{Mesh, Normals};
+use crate::model::{Model, ModelNode, ModelSkin};
+use crate::texture::{SamplerOptions, Texture};
+use crate::vertex::Vertices;
+
+// Imports the default scene of a glTF or GLB file, with its skins and animations. Materials are
+// imported as their base color, with the factor baked into the texture. Meshes keep their data
+// so that colliders can be built from them.
+pub async fn load(gfx: &Graphics<'_>, assets: &mut Assets, file_name: &str) -> Result<Model> {
+    let data = file::read_binary_asset(file_name).await?;
+    let gltf = Gltf::from_slice(&data).with_context(|| format!("Invalid glTF {file_name}"))?;
+    // External files are relative to the model
+    let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+
+    let mut buffers = Vec::new();
+    for b in gltf.buffers() {
+        let data = match b.source() {
+            buffer::Source::Bin => gltf.blob.clone().context("Missing GLB binary chunk")?,
+            buffer::Source::Uri(uri) => read_uri(dir, uri).await?,
+        };
+        if data.len() < b.length() {
+            bail!("Buffer {} is shorter than declared", b.index());
+        }
+        buffers.push(data);
+    }
+
+    let mut textures = HashMap::new();
+    for m in gltf.materials() {
+        let texture = base_color_texture(gfx, assets, &m, dir, &buffers)
+            .await
+            .and_then(|texture| {
+                TexturedMaterial::check_texture(gfx, assets, &texture)?;
+                Ok(texture)
+            })
+            .with_context(|| format!("Failed to load material {:?}", m.name()))?;
+        textures.insert(m.index(), assets.add_texture(texture));
+    }
+
+    let meshes = gltf
+        .meshes()
+        .map(|m| {
+            m.primitives()
+                .filter(|p| p.mode() == mesh::Mode::Triangles)
+                .map(|p| {
+                    let mesh = load_primitive(gfx, &p, &buffers)?;
+                    let material = p.material();
+                    let texture = match textures.get(&material.index()) {
+                        Some(&texture) => texture,
+                        // Primitives without material are white
+                        None => {
+                            let texture = Texture::new_2d_from_color(gfx, assets, [1.0; 4])?;
+                            let texture = assets.add_texture(texture);
+                            textures.insert(None, texture);
+                            texture
+                        }
+                    };
+                    Ok((assets.add_mesh(mesh), texture))
+                })
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Failed to load mesh {:?}", m.name()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let nodes = gltf
+        .nodes()
+        .map(|n| {
+            let (pos, [x, y, z, w], scale) = n.transform().decomposed();
+            let mut transform = Transform::new(Vec3::from(pos), Vec3::from(scale));
+            // Transforms store the inverse rotation
+            transform.set_rotation(UnitQuat::from_quaternion(Quat::new(w, x, y, z)).inverse());
+            ModelNode {
+                name: n.name().map(str::to_string),
+                transform,
+                primitives: n.mesh().map_or(Vec::new(), |m| meshes[m.index()].clone()),
+                children: n.children().map(|c| c.index()).collect(),
+                skin: n.skin().map(|s| s.index()),
+            }
+        })
+        .collect();
+
+    let skins = gltf
+        .skins()
+        .map(|s| {
+            load_skin(&s, &buffers).with_context(|| format!("Failed to load skin {:?}", s.name()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let animations = gltf
+        .animations()
+        .map(|a| {
+            load_animation(&a, &buffers)
+                .map(Arc::new)
+                .with_context(|| format!("Failed to load animation {:?}", a.name()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .with_context(|| format!("{file_name} has no scene"))?;
+
+    let roots = scene.nodes().map(|n| n.index()).collect();
+    Ok(Model::new(file_name, nodes, roots, skins, animations))
+}
+
+async fn read_uri(dir: &Path, uri: &str) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, data) = data
+            .split_once(";base64,")
+            .context("Only base64 data URIs are supported")?;
+        return Ok(base64::engine::general_purpose::STANDARD.decode(data)?);
+    }
+
+    let path = dir.join(uri);
+    let path = path.to_str().context("Invalid path")?;
+    file::read_binary_asset(path)
+        .await
+        .with_context(|| format!("Failed to read {path}"))
+}
+
+fn load_primitive(
+    gfx: &Graphics,
+    primitive: &mesh::Primitive,
+    buffers: &[Vec<u8>],
+) -> Result<Mesh> {
+    let reader = primitive.reader(|b| buffers.get(b.index()).map(Vec::as_slice));
+    let positions = reader
+        .read_positions()
+        .context("Missing positions")?
+        .collect::<Vec<_>>();
+    let mut indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect::<Vec<_>>(),
+    };
+    if let Some(&i) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        bail!("Index {i} out of range");
+    }
+
+    let count = positions.len();
+    // The set the base color texture is mapped with, the other one of the first two is kept as
+    // the second set
+    let tex_coord_set = primitive
+        .material()
+        .pbr_metallic_roughness()
+        .base_color_texture()
+        .map_or(0, |t| t.tex_coord());
+    let other_tex_coord_set = if tex_coord_set == 1 { 0 } else { 1 };
+    let mut vertices = Vertices {
+        positions,
+        tex_coords: match reader.read_tex_coords(tex_coord_set) {
+            Some(tex_coords) => tex_coords.into_f32().collect(),
+            None => vec![[0.0; 2]; count],
+        },
+        normals: reader.read_normals().map_or(Vec::new(), |n| n.collect()),
+        tangents: reader.read_tangents().map_or(Vec::new(), |t| t.collect()),
+        tex_coords_1: reader
+            .read_tex_coords(other_tex_coord_set)
+            .map_or(Vec::new(), |t| t.into_f32().collect()),
+        colors: reader
+            .read_colors(0)
+            .map_or(Vec::new(), |c| c.into_rgba_f32().collect()),
+        joints: reader
+            .read_joints(0)
+            .map_or(Vec::new(), |j| j.into_u16().collect()),
+        weights: reader
+            .read_weights(0)
+            .map_or(Vec::new(), |w| w.into_f32().collect()),
+    };
+    vertices.check()?;
+    // Required to be flat by the specification
+    if vertices.normals.is_empty() {
+        Normals::Flat.generate(&mut vertices, &mut indices);
+    }
+
+    Mesh::from_parts(gfx, &[(vertices, indices)], true)
+}
+
+fn load_skin(skin: &::gltf::Skin, buffers: &[Vec<u8>]) -> Result<ModelSkin> {
+    let joints = skin.joints().map(|j| j.index()).collect::<Vec<_>>();
+    if joints.len() > MAX_JOINTS {
+        bail!(
+            "{} joints, at most {MAX_JOINTS} are supported",
+            joints.len()
+        );
+    }
+
+    let reader = skin.reader(|b| buffers.get(b.index()).map(Vec::as_slice));
+    // Identity matrices when missing
+    let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices.map(Mat4::from).collect::<Vec<_>>(),
+        None => vec![Mat4::identity(); joints.len()],
+    };
+    if inverse_bind_matrices.len() != joints.len() {
+        bail!(
+            "{} inverse bind matrices for {} joints",
+            inverse_bind_matrices.len(),
+            joints.len()
+        );
+    }
+
+    Ok(ModelSkin {
+        joints,
+        inverse_bind_matrices,
+    })
+}
+
+// Morph target weights are not supported and skipped
+fn load_animation(animation: &::gltf::Animation, buffers: &[Vec<u8>]) -> Result<AnimationClip> {
+    use ::gltf::animation::util::ReadOutputs;
+
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let reader = channel.reader(|b| buffers.get(b.index()).map(Vec::as_slice));
+        let times = reader
+            .read_inputs()
+            .context("Missing keyframe times")?
+            .collect();
+        let (property, values) = match reader.read_outputs().context("Missing keyframe values")? {
+            ReadOutputs::Translations(t) => (
+                Property::Translation,
+                t.map(|[x, y, z]| Vec4::new(x, y, z, 0.0)).collect(),
+            ),
+            ReadOutputs::Rotations(r) => {
+                (Property::Rotation, r.into_f32().map(Vec4::from).collect())
+            }
+            ReadOutputs::Scales(s) => (
+                Property::Scale,
+                s.map(|[x, y, z]| Vec4::new(x, y, z, 0.0)).collect(),
+            ),
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+        let interpolation = match channel.sampler().interpolation() {
+            ::gltf::animation::Interpolation::Step => Interpolation::Step,
+            ::gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            ::gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+        let node = channel.target().node().index();
+        channels.push(Channel::new(node, property, interpolation, times, values)?);
+    }
+
+    let name = animation.name().map_or_else(
+        || format!("Animation {}", animation.index()),
+        str::to_string,
+    );
+    Ok(AnimationClip::new(name, channels))
+}
+
+async fn base_color_texture(
+    gfx: &Graphics<'_>,
+    assets: &Assets,
+    material: &::gltf::Material<'_>,
+    dir: &Path,
+    buffers: &[Vec<u8>],
+) -> Result<Texture> {
+    let pbr = material.pbr_metallic_roughness();
+    let factor = pbr.base_color_factor();
+    let Some(info) = pbr.base_color_texture() else {
+        return Texture::new_2d_from_color(gfx, assets, factor);
+    };
+
+    let texture = info.texture();
+    let data = match texture.source().source() {
+        image::Source::View { view, .. } => buffers
+            .get(view.buffer().index())
+            .and_then(|b| b.get(view.offset()..view.offset() + view.length()))
+            .with_context(|| format!("Image buffer view {} is out of bounds", view.index()))?
+            .to_vec(),
+        image::Source::Uri { uri, .. } => read_uri(dir, uri).await?,
+    };
+    let mut image = ::image::load_from_memory(&data)?.into_rgba8();
+    if factor != [1.0; 4] {
+        for pixel in image.pixels_mut() {
+            pixel.0 = multiply_srgb(pixel.0, factor);
+        }
+    }
+
+    Texture::new_2d_from_image(gfx, assets, &image, sampler_options(&texture.sampler()))
+}
+
+// Colors are multiplied in linear space, alpha is linear already
+fn multiply_srgb(color: [u8; 4], factor: [f32; 4]) -> [u8; 4] {
+    let mut res = [0; 4];
+    for i in 0..4 {
+        let c = color[i] as f32 / 255.0;
+        let c = if i < 3 {
+            linear_to_srgb(srgb_to_linear(c) * factor[i])
+        } else {
+            c * factor[i]
+        };
+        res[i] = (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    res
+}
+
+// Samplers only have one address mode, so the horizontal one is used
+fn sampler_options(sampler: &texture::Sampler) -> SamplerOptions {
+    use texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = match sampler.wrap_s() {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+        _ => wgpu::FilterMode::Linear,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear)
+        }
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => {
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+        }
+        Some(MinFilter::LinearMipmapLinear) | None => {
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear)
+        }
+    };
+    let all_linear = [mag_filter, min_filter, mipmap_filter]
+        .iter()
+        .all(|&f| f == wgpu::FilterMode::Linear);
+
+    SamplerOptions {
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+        anisotropy: if all_linear { 16 } else { 1 },
+        address_mode,
+    }
+}
@@ -1,11 +1,12 @@
 use std::ops::Deref;
 use std::sync::Arc;
 
-use wgpu::util::DeviceExt;
+use anyhow::*;
 
 use crate::assets::{Assets, MaterialHandle, MeshHandle};
 use crate::materials::{ApplyMaterial, Material};
 use crate::mesh::DrawMesh;
+use crate::profiler::Profiler;
 use crate::render_target::RenderTarget;
 use crate::texture::Texture;
 
@@ -19,12 +20,19 @@ pub struct RenderPipelineParams<'a> {
     pub vertex_buffer_layouts: &'a [wgpu::VertexBufferLayout<'a>],
 }
 
+struct Frame {
+    surface_tex: wgpu::SurfaceTexture,
+    surface_tex_view: wgpu::TextureView,
+}
+
 pub struct Graphics<'a> {
     surface: wgpu::Surface<'a>,
     surface_config: wgpu::SurfaceConfiguration,
     device: wgpu::Device,
     queue: wgpu::Queue,
     depth_tex: Texture,
+    frame: Option<Frame>,
+    profiler: Profiler,
 }
 
 impl<'a> Graphics<'a> {
@@ -47,6 +55,27 @@ impl<'a> Graphics<'a> {
         &self.queue
     }
 
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    pub fn surface_tex(&self) -> &wgpu::Texture {
+        &self
+            .frame
+            .as_ref()
+            .expect("Frame not started")
+            .surface_tex
+            .texture
+    }
+
+    pub fn surface_tex_view(&self) -> &wgpu::TextureView {
+        &self
+            .frame
+            .as_ref()
+            .expect("Frame not started")
+            .surface_tex_view
+    }
+
     pub async fn new(window: Arc<winit::window::Window>) -> Graphics<'a> {
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::PRIMARY,
@@ -66,11 +95,18 @@ impl<'a> Graphics<'a> {
             .await
             .unwrap();
 
+        // Optional: timestamps are used for profiling passes, BC for compressed textures and
+        // filtering of 32-bit floats for float textures, which are converted otherwise
+        let optional_features = adapter.features()
+            & (wgpu::Features::TIMESTAMP_QUERY
+                | wgpu::Features::TEXTURE_COMPRESSION_BC
+                | wgpu::Features::FLOAT32_FILTERABLE);
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
+                    required_features: optional_features,
                     required_limits: wgpu::Limits::default(),
                     memory_hints: wgpu::MemoryHints::default(),
                 },
@@ -91,8 +127,12 @@ impl<'a> Graphics<'a> {
                 .find(|f| f.is_srgb())
                 .unwrap_or(caps.formats[0]);
 
+            // Allows capturing screenshots
+            let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (caps.usages & wgpu::TextureUsages::COPY_SRC);
+
             wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                usage,
                 format,
                 width: surface_size.width,
                 height: surface_size.height,
@@ -105,6 +145,7 @@ impl<'a> Graphics<'a> {
         surface.configure(&device, &surface_config);
 
         let depth_tex = Texture::new_depth(&device, Self::DEPTH_TEX_FORMAT, surface_size.into());
+        let profiler = Profiler::new(&device, &queue);
 
         Self {
             surface_config,
@@ -112,6 +153,8 @@ impl<'a> Graphics<'a> {
             device,
             queue,
             depth_tex,
+            frame: None,
+            profiler,
         }
     }
 
@@ -125,39 +168,61 @@ impl<'a> Graphics<'a> {
         }
     }
 
+    pub fn begin_frame(&mut self) {
+        let surface_tex = self
+            .surface
+            .get_current_texture()
+            .expect("Missing surface texture");
+        let surface_tex_view = surface_tex
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.frame = Some(Frame {
+            surface_tex,
+            surface_tex_view,
+        });
+    }
+
+    pub fn end_frame(&mut self) {
+        self.profiler.end_frame(&self.device, &self.queue);
+        if let Some(frame) = self.frame.take() {
+            frame.surface_tex.present();
+        }
+    }
+
     pub fn build_render_bundle(
         &self,
         mesh: MeshHandle,
         material: MaterialHandle,
         rt: Option<&RenderTarget>,
         assets: &mut Assets,
-    ) -> wgpu::RenderBundle {
+    ) -> Result<wgpu::RenderBundle> {
         let mut encoder = self.new_bundle_encoder(rt);
-        match assets.material(material) {
+        let material = assets.material(material);
+        match material {
             Material::Color(m) => m.apply(&mut encoder),
             Material::Skybox(m) => m.apply(&mut encoder),
+            Material::Sky(m) => m.apply(&mut encoder),
             Material::Textured(m) => m.apply(&mut encoder),
             Material::PostProcess(m) => m.apply(&mut encoder),
         }
-        encoder.draw_mesh(assets.mesh(mesh));
-        encoder.finish(&wgpu::RenderBundleDescriptor { label: None })
+        encoder
+            .draw_mesh(assets.mesh(mesh), material.vertex_attributes())
+            .with_context(|| {
+                format!("Mesh can't be drawn with the {} material", material.name())
+            })?;
+        Ok(encoder.finish(&wgpu::RenderBundleDescriptor { label: None }))
     }
 
-    pub fn render_pass(&self, bundles: &[wgpu::RenderBundle], target: Option<&RenderTarget>) {
-        let surface_tex = target.is_none().then(|| {
-            self.surface
-                .get_current_texture()
-                .expect("Missing surface texture")
-        });
-        let surface_tex_view = surface_tex.as_ref().map(|t| {
-            t.texture
-                .create_view(&wgpu::TextureViewDescriptor::default())
-        });
-
-        let color_tex_view = target
-            .map(|t| t.color_tex().view())
-            .or(surface_tex_view.as_ref())
-            .unwrap();
+    pub fn render_pass(
+        &self,
+        name: &'static str,
+        bundles: &[wgpu::RenderBundle],
+        target: Option<&RenderTarget>,
+    ) {
+        let profiler_scope = self.profiler.begin_pass(name);
+
+        let color_tex_view =
+            target.map_or_else(|| self.surface_tex_view(), |t| t.color_tex().view());
         let color_attachment = Some(wgpu::RenderPassColorAttachment {
             view: color_tex_view,
             resolve_target: None,
@@ -189,7 +254,7 @@ impl<'a> Graphics<'a> {
                     color_attachments: &[color_attachment],
                     depth_stencil_attachment: depth_attachment,
                     occlusion_query_set: None,
-                    timestamp_writes: None,
+                    timestamp_writes: self.profiler.timestamp_writes(&profiler_scope),
                 });
 
                 pass.execute_bundles(bundles.iter());
@@ -199,76 +264,16 @@ impl<'a> Graphics<'a> {
         };
 
         self.queue.submit(Some(cmd_buffer));
-        if let Some(t) = surface_tex {
-            t.present()
-        }
-    }
-
-    pub fn new_uniform_bind_group(
-        &self,
-        data: &[u8],
-    ) -> (wgpu::BindGroupLayout, wgpu::BindGroup, wgpu::Buffer) {
-        let buffer = self.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: data,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let layout = self.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: None,
-        });
-
-        let group = self.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
-            label: None,
-        });
-
-        (layout, group, buffer)
+        self.profiler.end_pass(profiler_scope);
     }
 
     pub fn new_texture_bind_group(
         &self,
+        layout: &wgpu::BindGroupLayout,
         texture: &Texture,
-        view_dimension: wgpu::TextureViewDimension,
-    ) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
-        let layout = self.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: None,
-        });
-
-        let group = self.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
+    ) -> wgpu::BindGroup {
+        self.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -280,9 +285,7 @@ impl<'a> Graphics<'a> {
                 },
             ],
             label: None,
-        });
-
-        (layout, group)
+        })
     }
 
     pub fn new_render_pipeline(&self, params: RenderPipelineParams<'_>) -> wgpu::RenderPipeline {
@@ -341,7 +344,7 @@ impl<'a> Graphics<'a> {
         })
     }
 
-    fn new_bundle_encoder(&self, target: Option<&RenderTarget>) -> wgpu::RenderBundleEncoder {
+    fn new_bundle_encoder(&self, target: Option<&RenderTarget>) -> wgpu::RenderBundleEncoder<'_> {
         let color_format = target.map_or(self.surface_texture_format(), |t| t.color_tex().format());
         let depth_format = target.map_or(self.depth_texture_format(), |t| t.depth_tex().format());
 
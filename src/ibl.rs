@@ -0,0 +1,481 @@
+use anyhow::*;
+use encase::ShaderType;
+
+use crate::graphics::Graphics;
+use crate::materials::{SkyParams, Uniform, UniformBuffer};
+use crate::math::Vec3;
+use crate::shader_reflection::ShaderReflection;
+
+// Direct and ambient light shared by all lit materials
+#[derive(ShaderType, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    // Towards the light, normalized
+    pub light_direction: Vec3,
+    // Scales the light from the environment maps
+    pub environment_intensity: f32,
+    pub light_color: Vec3,
+    // Added on top of the environment maps, e.g. light from the night sky
+    pub ambient: Vec3,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            light_direction: Vec3::new(0.5, 1.0, 0.3).normalize(),
+            environment_intensity: 1.0,
+            light_color: Vec3::from_element(0.6),
+            ambient: Vec3::zeros(),
+        }
+    }
+}
+
+// Roughness of the prefiltered mip level being rendered
+#[derive(ShaderType)]
+struct PrefilterParams {
+    roughness: f32,
+}
+
+// Image based lighting precomputed from an environment cubemap: irradiance for diffuse light,
+// mip levels of increasing roughness for specular light, and the BRDF lookup table of the
+// split sum approximation. Also holds the `Lighting` of the scene.
+pub struct Environment {
+    irradiance: wgpu::Texture,
+    prefiltered: wgpu::Texture,
+    brdf_lut: wgpu::Texture,
+    irradiance_view: wgpu::TextureView,
+    prefiltered_view: wgpu::TextureView,
+    brdf_lut_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    env_layout: wgpu::BindGroupLayout,
+    // Roughness of each prefiltered mip level
+    prefilter_params: Vec<Uniform<PrefilterParams>>,
+    pipeline_layouts: EnvironmentPipelines<wgpu::PipelineLayout>,
+    pipelines: EnvironmentPipelines<wgpu::RenderPipeline>,
+    lighting: Lighting,
+    lighting_buffer: UniformBuffer<Lighting>,
+}
+
+pub struct EnvironmentPipelines<T> {
+    irradiance: T,
+    prefilter: T,
+    brdf_lut: T,
+}
+
+impl Environment {
+    const CUBE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+    const IRRADIANCE_SIZE: u32 = 32;
+    const PREFILTERED_SIZE: u32 = 128;
+    // From roughness 0 at the first level to 1 at the last one
+    const PREFILTERED_MIP_LEVELS: u32 = 5;
+    const BRDF_LUT_SIZE: u32 = 128;
+
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_texture(0, 0, wgpu::TextureViewDimension::Cube)?;
+        reflection.check_sampler(0, 1)?;
+        reflection.check_uniform::<PrefilterParams>(1, 0)
+    }
+
+    // The maps are black until rendered from an environment
+    pub fn new(gfx: &Graphics, shader: &wgpu::ShaderModule, reflection: &ShaderReflection) -> Self {
+        let env_layout = reflection.bind_group_layout(gfx, 0);
+        let roughness_layout = reflection.bind_group_layout(gfx, 1);
+        let pipeline_layout = |bind_group_layouts: &[&wgpu::BindGroupLayout]| {
+            gfx.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            })
+        };
+        let pipeline_layouts = EnvironmentPipelines {
+            irradiance: pipeline_layout(&[&env_layout]),
+            prefilter: pipeline_layout(&[&env_layout, &roughness_layout]),
+            brdf_lut: pipeline_layout(&[]),
+        };
+        let pipelines = new_pipelines(gfx, &pipeline_layouts, shader);
+
+        let prefilter_params = (0..Self::PREFILTERED_MIP_LEVELS)
+            .map(|mip| {
+                let params = PrefilterParams {
+                    roughness: mip as f32 / (Self::PREFILTERED_MIP_LEVELS - 1) as f32,
+                };
+                Uniform::new(gfx, &roughness_layout, &params)
+            })
+            .collect();
+
+        let sampler = gfx.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let irradiance = new_cube(gfx, Self::IRRADIANCE_SIZE, 1);
+        let prefiltered = new_cube(gfx, Self::PREFILTERED_SIZE, Self::PREFILTERED_MIP_LEVELS);
+        let brdf_lut = gfx.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: Self::BRDF_LUT_SIZE,
+                height: Self::BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::BRDF_LUT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let cube_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            })
+        };
+
+        let lighting = Lighting::default();
+        let lighting_buffer = UniformBuffer::new(gfx, &lighting);
+
+        let env = Self {
+            irradiance_view: cube_view(&irradiance),
+            prefiltered_view: cube_view(&prefiltered),
+            brdf_lut_view: brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            sampler,
+            env_layout,
+            prefilter_params,
+            pipeline_layouts,
+            pipelines,
+            lighting,
+            lighting_buffer,
+        };
+        env.render_brdf_lut(gfx);
+        env
+    }
+
+    // Renders the irradiance and prefiltered maps from the environment cubemap into the existing
+    // textures, so bind groups made before stay valid
+    pub fn render(&self, gfx: &Graphics, env: &wgpu::TextureView) {
+        let env_bind_group = gfx.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.env_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(env),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        let mut encoder =
+            gfx.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        render_faces(
+            &mut encoder,
+            &self.pipelines.irradiance,
+            &[&env_bind_group],
+            &self.irradiance,
+            0,
+        );
+        for (mip, params) in self.prefilter_params.iter().enumerate() {
+            render_faces(
+                &mut encoder,
+                &self.pipelines.prefilter,
+                &[&env_bind_group, params.bind_group()],
+                &self.prefiltered,
+                mip as u32,
+            );
+        }
+        gfx.queue().submit(Some(encoder.finish()));
+    }
+
+    // Depends on the shader only
+    pub fn render_brdf_lut(&self, gfx: &Graphics) {
+        let mut encoder =
+            gfx.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        render_faces(
+            &mut encoder,
+            &self.pipelines.brdf_lut,
+            &[],
+            &self.brdf_lut,
+            0,
+        );
+        gfx.queue().submit(Some(encoder.finish()));
+    }
+
+    // Creates new pipelines from the given shader without applying them
+    pub fn rebuild_pipelines(
+        &self,
+        gfx: &Graphics,
+        shader: &wgpu::ShaderModule,
+    ) -> EnvironmentPipelines<wgpu::RenderPipeline> {
+        new_pipelines(gfx, &self.pipeline_layouts, shader)
+    }
+
+    // The maps are left as they are until rendered again
+    pub fn set_pipelines(&mut self, pipelines: EnvironmentPipelines<wgpu::RenderPipeline>) {
+        self.pipelines = pipelines;
+    }
+
+    pub fn set_lighting(&mut self, gfx: &Graphics, lighting: Lighting) {
+        if lighting != self.lighting {
+            self.lighting = lighting;
+            self.lighting_buffer.write(gfx, &lighting);
+        }
+    }
+
+    // Binds the irradiance cubemap, the prefiltered cubemap, the BRDF lookup table,
+    // a sampler for all of them and the lighting uniform in that order
+    pub fn new_bind_group(
+        &self,
+        gfx: &Graphics,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        gfx.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.irradiance_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.prefiltered_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.brdf_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.lighting_buffer.buffer().as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
+
+// Procedural sky rendered into a cubemap, to render the environment maps from
+pub struct SkyCube {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    params_uniform: Uniform<SkyParams>,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl SkyCube {
+    const SIZE: u32 = 64;
+    // Sun or moon movement after which the sky is worth rendering again
+    const MAX_ANGLE_CHANGE: f32 = 1.0 * std::f32::consts::PI / 180.0;
+
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_uniform::<SkyParams>(0, 0)
+    }
+
+    pub fn new(gfx: &Graphics, shader: &wgpu::ShaderModule, reflection: &ShaderReflection) -> Self {
+        let params_layout = reflection.bind_group_layout(gfx, 0);
+        let pipeline_layout = gfx.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&params_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = new_pipeline(
+            gfx,
+            &pipeline_layout,
+            shader,
+            "fs_main",
+            Environment::CUBE_FORMAT,
+        );
+
+        // Full mip chain for the environment passes to read blurrier levels from
+        let mip_level_count = Self::SIZE.ilog2() + 1;
+        let texture = new_cube(gfx, Self::SIZE, mip_level_count);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            params_uniform: Uniform::new(gfx, &params_layout, &SkyParams::default()),
+            pipeline_layout,
+            pipeline,
+        }
+    }
+
+    // Whether the sky changed enough since it was rendered with `old` to render it again
+    pub fn needs_render(old: &SkyParams, new: &SkyParams) -> bool {
+        let moved = |a: &Vec3, b: &Vec3| a.angle(b) > Self::MAX_ANGLE_CHANGE;
+        moved(&old.sun_direction, &new.sun_direction)
+            || moved(&old.moon_direction, &new.moon_direction)
+            || old.turbidity != new.turbidity
+            || old.ground_color != new.ground_color
+    }
+
+    // Renders the first mip level, the others need to be generated afterwards
+    pub fn render(&mut self, gfx: &Graphics, params: &SkyParams) {
+        self.params_uniform.write(gfx, params);
+        let mut encoder =
+            gfx.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        render_faces(
+            &mut encoder,
+            &self.pipeline,
+            &[self.params_uniform.bind_group()],
+            &self.texture,
+            0,
+        );
+        gfx.queue().submit(Some(encoder.finish()));
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    // Creates a new pipeline from the given shader without applying it
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        new_pipeline(
+            gfx,
+            &self.pipeline_layout,
+            shader,
+            "fs_main",
+            Environment::CUBE_FORMAT,
+        )
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+}
+
+fn new_cube(gfx: &Graphics, size: u32, mip_level_count: u32) -> wgpu::Texture {
+    gfx.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Environment::CUBE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+fn new_pipelines(
+    gfx: &Graphics,
+    layouts: &EnvironmentPipelines<wgpu::PipelineLayout>,
+    shader: &wgpu::ShaderModule,
+) -> EnvironmentPipelines<wgpu::RenderPipeline> {
+    let cube_format = Environment::CUBE_FORMAT;
+    EnvironmentPipelines {
+        irradiance: new_pipeline(
+            gfx,
+            &layouts.irradiance,
+            shader,
+            "fs_irradiance",
+            cube_format,
+        ),
+        prefilter: new_pipeline(gfx, &layouts.prefilter, shader, "fs_prefilter", cube_format),
+        brdf_lut: new_pipeline(
+            gfx,
+            &layouts.brdf_lut,
+            shader,
+            "fs_brdf_lut",
+            Environment::BRDF_LUT_FORMAT,
+        ),
+    }
+}
+
+fn new_pipeline(
+    gfx: &Graphics,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry_point: &str,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    gfx.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fragment_entry_point,
+            targets: &[Some(format.into())],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+// Renders every layer of the mip level, passing the layer as the instance index
+fn render_faces(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    texture: &wgpu::Texture,
+    mip: u32,
+) {
+    for layer in 0..texture.depth_or_array_layers() {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        pass.draw(0..3, layer..layer + 1);
+    }
+}
@@ -14,6 +14,8 @@ pub enum InputAction {
     ControlPlayer,
     Spawn,
     Grab,
+    Screenshot,
+    Record,
 }
 
 #[derive(Eq, PartialEq, Hash, Copy, Clone)]
@@ -121,5 +123,7 @@ fn action_key(action: InputAction) -> Key {
         InputAction::ControlPlayer => Key::Keyboard(KeyCode::Tab),
         InputAction::Spawn => Key::Keyboard(KeyCode::KeyF),
         InputAction::Grab => Key::MouseButton(MouseButton::Left),
+        InputAction::Screenshot => Key::Keyboard(KeyCode::F12),
+        InputAction::Record => Key::Keyboard(KeyCode::F9),
     }
 }
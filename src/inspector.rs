@@ -0,0 +1,336 @@
+use std::collections::HashSet;
+
+use hecs::{Entity, World};
+use rapier3d::prelude::RigidBodyType;
+
+use crate::assets::{Assets, MaterialHandle};
+use crate::components::{
+    AnimationPlayer, Camera, Material, Mesh, Name, Parent, Player, PlayerTarget, RenderOrder,
+    RenderTags, RigidBody, Transform, Tween, TweenMode, RENDER_TAG_DEBUG_UI, RENDER_TAG_HIDDEN,
+    RENDER_TAG_POST_PROCESS, RENDER_TAG_SCENE,
+};
+use crate::materials;
+use crate::math::{UnitQuat, Vec3};
+use crate::physics::Physics;
+
+const RENDER_TAGS: [(&str, u32); 4] = [
+    ("Scene", RENDER_TAG_SCENE),
+    ("Post-process", RENDER_TAG_POST_PROCESS),
+    ("Hidden", RENDER_TAG_HIDDEN),
+    ("Debug UI", RENDER_TAG_DEBUG_UI),
+];
+
+const BODY_TYPES: [(&str, RigidBodyType); 4] = [
+    ("Dynamic", RigidBodyType::Dynamic),
+    ("Fixed", RigidBodyType::Fixed),
+    (
+        "Kinematic (position)",
+        RigidBodyType::KinematicPositionBased,
+    ),
+    (
+        "Kinematic (velocity)",
+        RigidBodyType::KinematicVelocityBased,
+    ),
+];
+
+const TWEEN_MODES: [(&str, TweenMode); 3] = [
+    ("Once", TweenMode::Once),
+    ("Loop", TweenMode::Loop),
+    ("Ping-pong", TweenMode::PingPong),
+];
+
+// Lists world entities and allows editing their components at runtime
+#[derive(Default)]
+pub struct Inspector {
+    selected: Option<Entity>,
+}
+
+impl Inspector {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        world: &mut World,
+        physics: &mut Physics,
+        assets: &Assets,
+    ) {
+        if self.selected.is_some_and(|e| !world.contains(e)) {
+            self.selected = None;
+        }
+
+        egui::Window::new("Inspector")
+            .default_width(300.0)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_salt("entities")
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for e in world.iter().map(|e| e.entity()) {
+                            let label = match world.get::<&Name>(e) {
+                                Ok(name) => format!("{} ({:?})", name.0, e),
+                                Err(_) => format!("{} ({:?})", entity_kind(world, e), e),
+                            };
+                            if ui
+                                .selectable_label(self.selected == Some(e), label)
+                                .clicked()
+                            {
+                                self.selected = Some(e);
+                            }
+                        }
+                    });
+
+                if let Some(e) = self.selected {
+                    ui.separator();
+                    egui::ScrollArea::vertical()
+                        .id_salt("components")
+                        .show(ui, |ui| show_components(ui, e, world, physics, assets));
+                }
+            });
+    }
+}
+
+fn entity_kind(world: &World, e: Entity) -> &'static str {
+    if world.satisfies::<&Player>(e).unwrap_or(false) {
+        "Player"
+    } else if world.satisfies::<&PlayerTarget>(e).unwrap_or(false) {
+        "Player target"
+    } else if world.satisfies::<&Camera>(e).unwrap_or(false) {
+        "Camera"
+    } else if world.satisfies::<&RigidBody>(e).unwrap_or(false) {
+        "Body"
+    } else {
+        "Entity"
+    }
+}
+
+fn show_components(
+    ui: &mut egui::Ui,
+    e: Entity,
+    world: &World,
+    physics: &mut Physics,
+    assets: &Assets,
+) {
+    // The transform of children is overwritten from the local one
+    if let Ok(mut parent) = world.get::<&mut Parent>(e) {
+        ui.collapsing("Transform (local)", |ui| {
+            transform_edit(ui, &mut parent.local)
+        });
+    } else if let Ok(mut tr) = world.get::<&mut Transform>(e) {
+        ui.collapsing("Transform", |ui| {
+            if transform_edit(ui, &mut tr) {
+                if let Ok(body) = world.get::<&RigidBody>(e) {
+                    body.set_pose(physics, tr.position(), tr.rotation(), tr.scale());
+                }
+            }
+        });
+    }
+
+    if let Ok(mut order) = world.get::<&mut RenderOrder>(e) {
+        ui.horizontal(|ui| {
+            ui.label("Render order");
+            ui.add(egui::DragValue::new(&mut order.0));
+        });
+    }
+
+    if let Ok(mut tags) = world.get::<&mut RenderTags>(e) {
+        ui.collapsing("Render tags", |ui| {
+            for (name, bit) in RENDER_TAGS {
+                let mut set = tags.0 & bit != 0;
+                if ui.checkbox(&mut set, name).changed() {
+                    tags.0 ^= bit;
+                }
+            }
+        });
+    }
+
+    if let Ok(mut mesh) = world.get::<&mut Mesh>(e) {
+        ui.horizontal(|ui| {
+            ui.label("Mesh");
+            egui::ComboBox::from_id_salt("mesh")
+                .selected_text(format!("{:?}", mesh.0))
+                .show_ui(ui, |ui| {
+                    for handle in assets.mesh_handles() {
+                        ui.selectable_value(&mut mesh.0, handle, format!("{:?}", handle));
+                    }
+                });
+        });
+    }
+
+    if world.get::<&Material>(e).is_ok() {
+        let choices = material_choices(world, e, assets);
+        let mut material = world.get::<&mut Material>(e).unwrap();
+        let label = |h| format!("{} {:?}", assets.material(h).name(), h);
+        ui.horizontal(|ui| {
+            ui.label("Material");
+            egui::ComboBox::from_id_salt("material")
+                .selected_text(label(material.0))
+                .show_ui(ui, |ui| {
+                    for handle in choices {
+                        ui.selectable_value(&mut material.0, handle, label(handle));
+                    }
+                });
+        });
+    }
+
+    // Meshes and materials are picked independently, so they might not go together
+    if let (Ok(mesh), Ok(material)) = (world.get::<&Mesh>(e), world.get::<&Material>(e)) {
+        let layout = assets.mesh(mesh.0).layout();
+        if let Err(e) = layout.check(assets.material(material.0).vertex_attributes()) {
+            ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+        }
+    }
+
+    if let Ok(mut body) = world.get::<&mut RigidBody>(e) {
+        ui.collapsing("Rigid body", |ui| {
+            let mut body_type = body.body_type();
+            egui::ComboBox::from_label("Type")
+                .selected_text(body_type_name(body_type))
+                .show_ui(ui, |ui| {
+                    for (name, ty) in BODY_TYPES {
+                        ui.selectable_value(&mut body_type, ty, name);
+                    }
+                });
+            if body_type != body.body_type() {
+                body.set_body_type(physics, body_type);
+            }
+
+            let rb = physics.bodies.get_mut(body.handle()).unwrap();
+
+            let mut linvel = *rb.linvel();
+            if vec3_edit(ui, "Linear velocity", &mut linvel, 0.05) {
+                rb.set_linvel(linvel, true);
+            }
+
+            let mut angvel = *rb.angvel();
+            if vec3_edit(ui, "Angular velocity", &mut angvel, 0.05) {
+                rb.set_angvel(angvel, true);
+            }
+
+            // Body mass gets recomputed from its colliders on the next physics step
+            let mut mass = rb.mass();
+            let colliders = rb.colliders().to_vec();
+            ui.horizontal(|ui| {
+                ui.label("Mass");
+                let response = ui.add(
+                    egui::DragValue::new(&mut mass)
+                        .speed(0.1)
+                        .range(0.001..=f32::MAX),
+                );
+                if response.changed() {
+                    for &c in &colliders {
+                        let c = physics.colliders.get_mut(c).unwrap();
+                        c.set_mass(mass / colliders.len() as f32);
+                    }
+                    physics.bodies.get_mut(body.handle()).unwrap().wake_up(true);
+                }
+            });
+        });
+    }
+
+    if let Ok(mut player) = world.get::<&mut AnimationPlayer>(e) {
+        ui.collapsing("Animation player", |ui| {
+            for (i, layer) in player.layers.iter_mut().enumerate() {
+                ui.push_id(i, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong(&layer.clip.name);
+                        ui.checkbox(&mut layer.looping, "Loop");
+                    });
+                    ui.add(egui::Slider::new(&mut layer.weight, 0.0..=1.0).text("Weight"));
+                    ui.add(egui::Slider::new(&mut layer.speed, -2.0..=2.0).text("Speed"));
+                    let duration = layer.clip.duration;
+                    ui.add(egui::Slider::new(&mut layer.time, 0.0..=duration).text("Time"));
+                });
+            }
+        });
+    }
+
+    if let Ok(mut tween) = world.get::<&mut Tween>(e) {
+        ui.collapsing("Tween", |ui| {
+            let mode_name = TWEEN_MODES
+                .iter()
+                .find(|(_, mode)| *mode == tween.mode)
+                .map(|(name, _)| *name)
+                .unwrap();
+            egui::ComboBox::from_label("Mode")
+                .selected_text(mode_name)
+                .show_ui(ui, |ui| {
+                    for (name, mode) in TWEEN_MODES {
+                        ui.selectable_value(&mut tween.mode, mode, name);
+                    }
+                });
+            ui.add(egui::Slider::new(&mut tween.speed, -2.0..=2.0).text("Speed"));
+            let period = tween.period();
+            ui.add(egui::Slider::new(&mut tween.time, 0.0..=period).text("Time"));
+        });
+    }
+}
+
+// Materials the entity can switch to, its own included. Those of other entities are left out since
+// their uniforms would be shared, as are sky and post-process ones made for screen quads and
+// ones the mesh lacks attributes for.
+fn material_choices(world: &World, e: Entity, assets: &Assets) -> Vec<MaterialHandle> {
+    let current = world.get::<&Material>(e).unwrap().0;
+    let used = world
+        .query::<&Material>()
+        .iter()
+        .filter(|&(other, _)| other != e)
+        .map(|(_, m)| m.0)
+        .collect::<HashSet<_>>();
+    let layout = world
+        .get::<&Mesh>(e)
+        .ok()
+        .map(|m| assets.mesh(m.0).layout());
+
+    assets
+        .material_handles()
+        .filter(|&h| {
+            let material = assets.material(h);
+            h == current
+                || (!used.contains(&h)
+                    && matches!(
+                        material,
+                        materials::Material::Color(_) | materials::Material::Textured(_)
+                    )
+                    && layout.is_none_or(|l| l.check(material.vertex_attributes()).is_ok()))
+        })
+        .collect()
+}
+
+fn body_type_name(body_type: RigidBodyType) -> &'static str {
+    BODY_TYPES
+        .iter()
+        .find(|(_, ty)| *ty == body_type)
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+fn transform_edit(ui: &mut egui::Ui, tr: &mut Transform) -> bool {
+    let mut pos = tr.position();
+    let (roll, pitch, yaw) = tr.rotation().euler_angles();
+    let mut angles = Vec3::new(roll, pitch, yaw).map(f32::to_degrees);
+    let mut scale = tr.scale();
+
+    let mut changed = false;
+    changed |= vec3_edit(ui, "Position", &mut pos, 0.05);
+    changed |= vec3_edit(ui, "Rotation", &mut angles, 1.0);
+    changed |= vec3_edit(ui, "Scale", &mut scale, 0.01);
+
+    if changed {
+        let angles = angles.map(f32::to_radians);
+        tr.set_position(pos);
+        tr.set_rotation(UnitQuat::from_euler_angles(angles.x, angles.y, angles.z));
+        tr.set_scale(scale);
+    }
+    changed
+}
+
+fn vec3_edit(ui: &mut egui::Ui, label: &str, v: &mut Vec3, speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        for c in v.iter_mut() {
+            changed |= ui.add(egui::DragValue::new(c).speed(speed)).changed();
+        }
+        changed
+    })
+    .inner
+}
@@ -0,0 +1,225 @@
+use std::io::Read;
+
+use ::ktx2::{ColorModel, DfdBlockBasic, Format, Reader, SupercompressionScheme, TransferFunction};
+use anyhow::*;
+use basis_universal::{
+    DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscoderBlockFormat,
+};
+
+use crate::texture::TextureData;
+
+// Reads a 2D, array or cubemap KTX2 texture with the mip levels stored in the file. UASTC
+// payloads are transcoded to BC7, which is decompressed on upload if the device lacks support.
+pub fn decode(data: &[u8]) -> Result<TextureData> {
+    let reader = Reader::new(data).map_err(|e| anyhow!("Invalid KTX2 file: {e}"))?;
+    let header = reader.header();
+
+    match header.supercompression_scheme {
+        None | Some(SupercompressionScheme::Zstandard) => (),
+        Some(SupercompressionScheme::BasisLZ) => {
+            bail!("Basis Universal ETC1S textures are not supported, export them as UASTC")
+        }
+        Some(scheme) => bail!("Unsupported supercompression {scheme:?}"),
+    }
+
+    if header.pixel_depth > 1 {
+        bail!("Volume textures are not supported");
+    }
+
+    // 1D textures have a height of 0
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+    let cube = match header.face_count {
+        1 => false,
+        6 => true,
+        n => bail!("Invalid face count {n}"),
+    };
+    let layer_count = header.layer_count.max(1) * header.face_count;
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let max_mips = size.max_mips(wgpu::TextureDimension::D2);
+    // 0 asks for mip levels to be generated at load time, only the base level is used instead
+    let mip_level_count = header.level_count.max(1);
+    if mip_level_count > max_mips {
+        bail!("{mip_level_count} mip levels specified for a {width}x{height} texture");
+    }
+
+    // UASTC files have no Vulkan format
+    let (format, uastc) = match header.format {
+        Some(format) => (texture_format(format)?, None),
+        None => {
+            let uastc = Uastc::new(&reader)?;
+            (uastc.format, Some(uastc))
+        }
+    };
+
+    let mut texture = TextureData {
+        width,
+        height,
+        layer_count,
+        mip_level_count,
+        cube,
+        format,
+        data: Vec::new(),
+    };
+
+    // Each level holds all layers, reordered so that each layer holds all levels
+    let mut levels = Vec::with_capacity(mip_level_count as usize);
+    for (mip, level) in reader.levels().enumerate() {
+        let mut data = match header.supercompression_scheme {
+            Some(SupercompressionScheme::Zstandard) => {
+                let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                ruzstd::decoding::StreamingDecoder::new(level.data)
+                    .map_err(|e| anyhow!("Invalid Zstandard data at mip level {mip}: {e}"))?
+                    .read_to_end(&mut data)
+                    .with_context(|| format!("Invalid Zstandard data at mip level {mip}"))?;
+                data
+            }
+            _ => level.data.to_vec(),
+        };
+        if let Some(uastc) = &uastc {
+            data = uastc
+                .transcode(&texture, mip as u32, &data)
+                .with_context(|| format!("Failed to transcode mip level {mip}"))?;
+        }
+
+        let expected_len = texture.mip_len(mip as u32) * layer_count as usize;
+        if data.len() < expected_len {
+            bail!(
+                "Truncated mip level {mip}: expected {expected_len} bytes, found {}",
+                data.len()
+            );
+        }
+        levels.push(data);
+    }
+
+    texture.data.reserve(texture.expected_data_len());
+    for layer in 0..layer_count as usize {
+        for (mip, level) in levels.iter().enumerate() {
+            let len = texture.mip_len(mip as u32);
+            texture
+                .data
+                .extend_from_slice(&level[layer * len..(layer + 1) * len]);
+        }
+    }
+
+    Ok(texture)
+}
+
+struct Uastc {
+    has_alpha: bool,
+    format: wgpu::TextureFormat,
+}
+
+impl Uastc {
+    fn new(reader: &Reader<&[u8]>) -> Result<Self> {
+        let dfd = reader
+            .dfd_blocks()
+            .next()
+            .ok_or_else(|| anyhow!("Missing data format descriptor"))?;
+        let dfd = DfdBlockBasic::parse(dfd.data)
+            .map_err(|e| anyhow!("Invalid data format descriptor: {e}"))?;
+        if dfd.header.color_model != Some(ColorModel::UASTC) {
+            bail!(
+                "Undefined format with color model {:?}",
+                dfd.header.color_model
+            );
+        }
+
+        // Channel ids from the KTX2 spec: RGB = 0, RGBA = 3, RRR = 4, RRRG = 5, RG = 6
+        let has_alpha = dfd
+            .sample_information()
+            .next()
+            .is_some_and(|s| matches!(s.channel_type, 3 | 5 | 6));
+        let format = match dfd.header.transfer_function {
+            Some(TransferFunction::SRGB) => wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+            _ => wgpu::TextureFormat::Bc7RgbaUnorm,
+        };
+
+        Ok(Self { has_alpha, format })
+    }
+
+    // Transcodes all layers of a mip level. Uncompressed targets aren't used since
+    // `basis_universal` allocates too small an output buffer for them.
+    fn transcode(&self, texture: &TextureData, mip: u32, data: &[u8]) -> Result<Vec<u8>> {
+        const UASTC_BLOCK_LEN: usize = 16;
+
+        let width = (texture.width >> mip).max(1);
+        let height = (texture.height >> mip).max(1);
+        let (num_blocks_x, num_blocks_y) = (width.div_ceil(4), height.div_ceil(4));
+        let slice_len = (num_blocks_x * num_blocks_y) as usize * UASTC_BLOCK_LEN;
+        let layer_count = texture.layer_count as usize;
+        if data.len() < slice_len * layer_count {
+            bail!(
+                "Truncated UASTC data: expected {} bytes, found {}",
+                slice_len * layer_count,
+                data.len()
+            );
+        }
+
+        let transcoder = LowLevelUastcTranscoder::new();
+        let mut transcoded = Vec::with_capacity(texture.mip_len(mip) * layer_count);
+        for slice in data.chunks_exact(slice_len).take(layer_count) {
+            let params = SliceParametersUastc {
+                num_blocks_x,
+                num_blocks_y,
+                has_alpha: self.has_alpha,
+                original_width: width,
+                original_height: height,
+            };
+            let slice = transcoder
+                .transcode_slice(
+                    slice,
+                    params,
+                    DecodeFlags::HIGH_QUALITY,
+                    TranscoderBlockFormat::BC7,
+                )
+                .map_err(|e| anyhow!("{e:?}"))?;
+            transcoded.extend_from_slice(&slice);
+        }
+        Ok(transcoded)
+    }
+}
+
+fn texture_format(format: Format) -> Result<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+
+    Ok(match format {
+        Format::R8G8B8A8_UNORM => F::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => F::Rgba8UnormSrgb,
+        Format::R8G8B8A8_SNORM => F::Rgba8Snorm,
+        Format::B8G8R8A8_UNORM => F::Bgra8Unorm,
+        Format::B8G8R8A8_SRGB => F::Bgra8UnormSrgb,
+        Format::R8_UNORM => F::R8Unorm,
+        Format::R8_SNORM => F::R8Snorm,
+        Format::R8G8_UNORM => F::Rg8Unorm,
+        Format::R8G8_SNORM => F::Rg8Snorm,
+        Format::A2B10G10R10_UNORM_PACK32 => F::Rgb10a2Unorm,
+        Format::B10G11R11_UFLOAT_PACK32 => F::Rg11b10Float,
+        Format::E5B9G9R9_UFLOAT_PACK32 => F::Rgb9e5Ufloat,
+        Format::R16_SFLOAT => F::R16Float,
+        Format::R16G16_SFLOAT => F::Rg16Float,
+        Format::R16G16B16A16_SFLOAT => F::Rgba16Float,
+        Format::R32_SFLOAT => F::R32Float,
+        Format::R32G32_SFLOAT => F::Rg32Float,
+        Format::R32G32B32A32_SFLOAT => F::Rgba32Float,
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => F::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => F::Bc1RgbaUnormSrgb,
+        Format::BC2_UNORM_BLOCK => F::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => F::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => F::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => F::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => F::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => F::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => F::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => F::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => F::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => F::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => F::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => F::Bc7RgbaUnormSrgb,
+        _ => bail!("Unsupported format {format:?}"),
+    })
+}
@@ -11,25 +11,45 @@ use crate::assets::Assets;
 use crate::frame_time::FrameTime;
 use crate::graphics::{Graphics, SurfaceSize};
 use crate::input::{Input, InputAction};
+use crate::recording::{Recorder, RecordingFormat};
 use crate::scene::Scene;
+use crate::ui::Ui;
 
+mod animation;
 mod assets;
+mod block_compression;
+mod capture;
 mod components;
+mod dds;
+mod equirect;
 mod file;
 mod frame_time;
+mod gltf;
 mod graphics;
+mod ibl;
 mod input;
+mod inspector;
+mod ktx2;
 mod materials;
 mod math;
 mod mesh;
+mod mipmaps;
+mod model;
+mod obj;
 mod physics;
+mod primitives;
+mod profiler;
+mod recording;
 mod render_target;
 mod scene;
+mod shader_preprocessor;
+mod shader_reflection;
+mod terrain;
 mod texture;
+mod time_of_day;
+mod ui;
 mod vertex;
-
-// TODO egui (https://github.com/ejb004/egui-wgpu-demo) or other UI.
-// Currently they all seem unusable after the recent update of winit to versions 0.29 - 0.30
+mod watcher;
 
 // TODO Spawned boxes should be rotated based on the camera view.
 // TODO Dragging should maintain box rotation relative to the camera.
@@ -43,8 +63,27 @@ struct State<'a> {
     assets: Option<Assets>,
     scene: Option<Scene>,
     input: Option<Input>,
+    ui: Option<Ui>,
     frame_time: Option<FrameTime>,
     new_canvas_size: Option<SurfaceSize>,
+    recording_format: RecordingFormat,
+    record_at_startup: bool,
+    recorder: Option<Recorder>,
+}
+
+impl<'a> State<'a> {
+    fn toggle_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.stop() {
+                eprintln!("Failed to save recording: {e}");
+            }
+        } else {
+            match Recorder::start(self.recording_format) {
+                Ok(recorder) => self.recorder = Some(recorder),
+                Err(e) => eprintln!("Failed to start recording: {e}"),
+            }
+        }
+    }
 }
 
 impl<'a> ApplicationHandler for State<'a> {
@@ -74,9 +113,14 @@ impl<'a> ApplicationHandler for State<'a> {
         self.scene = Some(Scene::new(&gfx, &mut assets));
         self.frame_time = Some(FrameTime::new());
         self.input = Some(Input::new());
+        self.ui = Some(Ui::new(&gfx, &window));
         self.window = Some(window);
         self.assets = Some(assets);
         self.gfx = Some(gfx);
+
+        if self.record_at_startup {
+            self.toggle_recording();
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
@@ -84,6 +128,12 @@ impl<'a> ApplicationHandler for State<'a> {
             return;
         }
 
+        let ui_consumed = self
+            .ui
+            .as_mut()
+            .unwrap()
+            .handle_event(self.window.as_ref().unwrap(), &event);
+
         match event {
             WindowEvent::RedrawRequested => {
                 // TODO Avoid this moving-out-and-moving-back-in
@@ -91,17 +141,29 @@ impl<'a> ApplicationHandler for State<'a> {
                 let mut gfx = self.gfx.take().unwrap();
                 let mut input = self.input.take().unwrap();
                 let mut assets = self.assets.take().unwrap();
+                let mut ui = self.ui.take().unwrap();
                 let window = self.window.take().unwrap();
 
                 if input.action_activated(InputAction::Quit) {
                     event_loop.exit();
                 }
 
+                if input.action_activated(InputAction::Record) {
+                    self.toggle_recording();
+                }
+
                 if let Some(&size) = self.new_canvas_size.as_ref() {
                     gfx.resize(size);
                 }
 
+                assets.reload_changed_shaders(&gfx);
+
                 let dt = self.frame_time.as_mut().unwrap().advance();
+                // Recorded frames advance by a fixed step regardless of how long capturing takes
+                let dt = self.recorder.as_ref().map_or(dt, |r| r.dt());
+
+                ui.begin_frame(&window);
+                scene.update_ui(dt, &gfx, &ui, &mut assets);
 
                 scene.update(
                     dt,
@@ -112,13 +174,22 @@ impl<'a> ApplicationHandler for State<'a> {
                     &self.new_canvas_size,
                 );
 
-                scene.render(&gfx, &mut assets);
+                gfx.begin_frame();
+                scene.render(&gfx, &mut assets, &mut ui, &window);
+                if let Some(recorder) = self.recorder.as_mut() {
+                    if let Err(e) = recorder.record(&gfx, gfx.surface_tex()) {
+                        eprintln!("Recording failed: {e}");
+                        self.recorder = None;
+                    }
+                }
+                gfx.end_frame();
 
                 input.clear();
                 // TODO Needed? Is there a better way?
                 window.request_redraw();
 
                 self.assets = Some(assets);
+                self.ui = Some(ui);
                 self.input = Some(input);
                 self.window = Some(window);
                 self.gfx = Some(gfx);
@@ -134,14 +205,14 @@ impl<'a> ApplicationHandler for State<'a> {
                         ..
                     },
                 ..
-            } => {
+            } if !ui_consumed => {
                 self.input
                     .as_mut()
                     .unwrap()
                     .consume_keyboard_event(code, state == ElementState::Pressed);
             }
 
-            WindowEvent::MouseInput { button, state, .. } => {
+            WindowEvent::MouseInput { button, state, .. } if !ui_consumed => {
                 self.input
                     .as_mut()
                     .unwrap()
@@ -171,6 +242,12 @@ impl<'a> ApplicationHandler for State<'a> {
         }
     }
 
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if self.recorder.is_some() {
+            self.toggle_recording();
+        }
+    }
+
     fn device_event(
         &mut self,
         _event_loop: &ActiveEventLoop,
@@ -178,11 +255,15 @@ impl<'a> ApplicationHandler for State<'a> {
         event: DeviceEvent,
     ) {
         match event {
-            DeviceEvent::MouseMotion { delta } => self
-                .input
-                .as_mut()
-                .unwrap()
-                .consume_mouse_delta(delta.0 as f32, delta.1 as f32),
+            // Dragging a widget should not turn the camera
+            DeviceEvent::MouseMotion { delta }
+                if !self.ui.as_ref().is_some_and(Ui::is_using_pointer) =>
+            {
+                self.input
+                    .as_mut()
+                    .unwrap()
+                    .consume_mouse_delta(delta.0 as f32, delta.1 as f32)
+            }
 
             DeviceEvent::MouseWheel { .. } => (),
 
@@ -192,10 +273,29 @@ impl<'a> ApplicationHandler for State<'a> {
 }
 
 fn main() {
+    let mut state = State::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => state.record_at_startup = true,
+            "--record-format" => match args.next().unwrap_or_default().parse() {
+                Ok(format) => state.recording_format = format,
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    return;
+                }
+            },
+            _ => {
+                eprintln!("Error: unknown argument '{arg}'");
+                return;
+            }
+        }
+    }
+
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut state = State::default();
     if let Err(e) = event_loop.run_app(&mut state) {
         eprintln!("Error: {e}");
     }
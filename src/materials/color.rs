@@ -1,63 +1,98 @@
-use crate::assets::Assets;
+use anyhow::Result;
+
+use crate::assets::{Assets, ShaderHandle};
 use crate::components::{Camera, Transform};
 use crate::graphics::{Graphics, RenderPipelineParams};
 use crate::math::Vec3;
-use crate::vertex::PosTexCoordNormalVertex;
+use crate::shader_reflection::ShaderReflection;
+use crate::vertex::VertexAttribute;
 
 use super::apply_material::ApplyMaterial;
-use super::uniforms::{Vec3Uniform, WorldViewProjUniform};
+use super::uniforms::{Uniform, WorldViewProjUniform};
 
 pub struct ColorMaterial {
     pipeline: wgpu::RenderPipeline,
-    matrices_uniform: WorldViewProjUniform,
-    matrices_uniform_buf: wgpu::Buffer,
-    matrices_uniform_bind_group: wgpu::BindGroup,
-    color_uniform: Vec3Uniform,
-    color_uniform_buf: wgpu::Buffer,
-    color_uniform_bind_group: wgpu::BindGroup,
+    shader: ShaderHandle,
+    bind_group_layouts: [wgpu::BindGroupLayout; 2],
+    matrices_uniform: Uniform<WorldViewProjUniform>,
+    color_uniform: Uniform<Vec3>,
 }
 
 impl ColorMaterial {
+    const MATRICES_GROUP: u32 = 0;
+    const COLOR_GROUP: u32 = 1;
+    pub const VERTEX_ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute::Position];
+
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_vertex_inputs(Self::VERTEX_ATTRIBUTES)?;
+        reflection.check_uniform::<WorldViewProjUniform>(Self::MATRICES_GROUP, 0)?;
+        reflection.check_uniform::<Vec3>(Self::COLOR_GROUP, 0)
+    }
+
     pub fn new(gfx: &Graphics, assets: &Assets) -> Self {
-        let matrices_uniform = WorldViewProjUniform::default();
-        let (matrices_uniform_bind_group_layout, matrices_uniform_bind_group, matrices_uniform_buf) =
-            gfx.new_uniform_bind_group(bytemuck::cast_slice(&[matrices_uniform]));
+        let shader = assets.color_shader;
+        let reflection = assets.shader_reflection(shader);
+        let bind_group_layouts = [
+            reflection.bind_group_layout(gfx, Self::MATRICES_GROUP),
+            reflection.bind_group_layout(gfx, Self::COLOR_GROUP),
+        ];
 
-        let color_uniform = Vec3Uniform::default();
-        let (color_uniform_bind_group_layout, color_uniform_bind_group, color_uniform_buf) =
-            gfx.new_uniform_bind_group(bytemuck::cast_slice(&[color_uniform]));
+        let matrices_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::MATRICES_GROUP as usize],
+            &WorldViewProjUniform::default(),
+        );
+        let color_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::COLOR_GROUP as usize],
+            &Vec3::new(0.0, 0.0, 1.0),
+        );
 
-        let pipeline = gfx.new_render_pipeline(RenderPipelineParams {
-            shader_module: assets.shader(assets.color_shader),
-            depth_write: true,
-            depth_enabled: true,
-            bind_group_layouts: &[
-                &matrices_uniform_bind_group_layout,
-                &color_uniform_bind_group_layout,
-            ],
-            vertex_buffer_layouts: &[PosTexCoordNormalVertex::buffer_layout()],
-        });
+        let pipeline = Self::new_pipeline(gfx, assets.shader(shader), &bind_group_layouts);
 
         Self {
             pipeline,
+            shader,
+            bind_group_layouts,
             matrices_uniform,
-            matrices_uniform_buf,
-            matrices_uniform_bind_group,
             color_uniform,
-            color_uniform_buf,
-            color_uniform_bind_group,
         }
     }
 }
 
 impl ColorMaterial {
+    pub fn shader(&self) -> ShaderHandle {
+        self.shader
+    }
+
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        Self::new_pipeline(gfx, shader_module, &self.bind_group_layouts)
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn new_pipeline(
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+        bind_group_layouts: &[wgpu::BindGroupLayout; 2],
+    ) -> wgpu::RenderPipeline {
+        gfx.new_render_pipeline(RenderPipelineParams {
+            shader_module,
+            depth_write: true,
+            depth_enabled: true,
+            bind_group_layouts: &[&bind_group_layouts[0], &bind_group_layouts[1]],
+            vertex_buffer_layouts: &VertexAttribute::buffer_layouts(Self::VERTEX_ATTRIBUTES),
+        })
+    }
+
     pub fn set_color(&mut self, gfx: &Graphics, color: Vec3) {
-        self.color_uniform.update(color);
-        gfx.queue().write_buffer(
-            &self.color_uniform_buf,
-            0,
-            bytemuck::cast_slice(&[self.color_uniform]),
-        );
+        self.color_uniform.write(gfx, &color);
     }
 
     pub fn set_wvp(
@@ -67,15 +102,13 @@ impl ColorMaterial {
         camera_transform: &Transform,
         transform: &Transform,
     ) {
-        self.matrices_uniform.update(
-            &transform.matrix(),
-            &camera_transform.view_matrix(),
-            &camera.proj_matrix(),
-        );
-        gfx.queue().write_buffer(
-            &self.matrices_uniform_buf,
-            0,
-            bytemuck::cast_slice(&[self.matrices_uniform]),
+        self.matrices_uniform.write(
+            gfx,
+            &WorldViewProjUniform::new(
+                &transform.matrix(),
+                &camera_transform.view_matrix(),
+                &camera.proj_matrix(),
+            ),
         );
     }
 }
@@ -83,7 +116,11 @@ impl ColorMaterial {
 impl ApplyMaterial for ColorMaterial {
     fn apply<'a>(&'a self, encoder: &mut wgpu::RenderBundleEncoder<'a>) {
         encoder.set_pipeline(&self.pipeline);
-        encoder.set_bind_group(0, &self.matrices_uniform_bind_group, &[]);
-        encoder.set_bind_group(1, &self.color_uniform_bind_group, &[]);
+        encoder.set_bind_group(
+            Self::MATRICES_GROUP,
+            self.matrices_uniform.bind_group(),
+            &[],
+        );
+        encoder.set_bind_group(Self::COLOR_GROUP, self.color_uniform.bind_group(), &[]);
     }
 }
@@ -1,8 +1,71 @@
-use super::{ColorMaterial, PostProcessMaterial, SkyboxMaterial, TexturedMaterial};
+use crate::assets::ShaderHandle;
+use crate::graphics::Graphics;
+use crate::vertex::VertexAttribute;
+
+use super::{ColorMaterial, PostProcessMaterial, SkyMaterial, SkyboxMaterial, TexturedMaterial};
 
 pub enum Material {
     Color(ColorMaterial),
     Skybox(SkyboxMaterial),
+    Sky(SkyMaterial),
     Textured(TexturedMaterial),
     PostProcess(PostProcessMaterial),
 }
+
+impl Material {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Color(_) => "Color",
+            Material::Skybox(_) => "Skybox",
+            Material::Sky(_) => "Sky",
+            Material::Textured(_) => "Textured",
+            Material::PostProcess(_) => "Post-process",
+        }
+    }
+
+    // Attributes meshes drawn with the material need, in the order of their vertex buffer slots
+    pub fn vertex_attributes(&self) -> &'static [VertexAttribute] {
+        match self {
+            Material::Color(_) => ColorMaterial::VERTEX_ATTRIBUTES,
+            Material::Skybox(_) => SkyboxMaterial::VERTEX_ATTRIBUTES,
+            Material::Sky(_) => SkyMaterial::VERTEX_ATTRIBUTES,
+            Material::Textured(m) => m.vertex_attributes(),
+            Material::PostProcess(_) => PostProcessMaterial::VERTEX_ATTRIBUTES,
+        }
+    }
+
+    pub fn shader(&self) -> ShaderHandle {
+        match self {
+            Material::Color(m) => m.shader(),
+            Material::Skybox(m) => m.shader(),
+            Material::Sky(m) => m.shader(),
+            Material::Textured(m) => m.shader(),
+            Material::PostProcess(m) => m.shader(),
+        }
+    }
+
+    // Creates a new pipeline from the given shader without applying it
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        match self {
+            Material::Color(m) => m.rebuild_pipeline(gfx, shader_module),
+            Material::Skybox(m) => m.rebuild_pipeline(gfx, shader_module),
+            Material::Sky(m) => m.rebuild_pipeline(gfx, shader_module),
+            Material::Textured(m) => m.rebuild_pipeline(gfx, shader_module),
+            Material::PostProcess(m) => m.rebuild_pipeline(gfx, shader_module),
+        }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::RenderPipeline) {
+        match self {
+            Material::Color(m) => m.set_pipeline(pipeline),
+            Material::Skybox(m) => m.set_pipeline(pipeline),
+            Material::Sky(m) => m.set_pipeline(pipeline),
+            Material::Textured(m) => m.set_pipeline(pipeline),
+            Material::PostProcess(m) => m.set_pipeline(pipeline),
+        }
+    }
+}
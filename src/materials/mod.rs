@@ -1,14 +1,18 @@
 pub use apply_material::ApplyMaterial;
 pub use color::ColorMaterial;
 pub use material::Material;
-pub use post_process::PostProcessMaterial;
+pub use post_process::{Fog, PostProcessMaterial};
+pub use sky::{SkyMaterial, SkyParams};
 pub use skybox::SkyboxMaterial;
 pub use textured::TexturedMaterial;
+pub use uniforms::MAX_JOINTS;
+pub(crate) use uniforms::{Uniform, UniformBuffer};
 
 mod apply_material;
 mod color;
 mod material;
 mod post_process;
+mod sky;
 mod skybox;
 mod textured;
 mod uniforms;
@@ -1,33 +1,213 @@
-use wgpu::{BindGroup, RenderPipeline};
+use anyhow::Result;
+use encase::ShaderType;
+use wgpu::{BindGroup, BindGroupLayout, RenderPipeline};
 
-use crate::assets::Assets;
+use crate::assets::{Assets, ShaderHandle};
+use crate::components::{Camera, Transform};
 use crate::graphics::{Graphics, RenderPipelineParams};
+use crate::math::{Mat4, Vec3, OPENGL_TO_WGPU_MATRIX};
+use crate::render_target::RenderTarget;
+use crate::shader_reflection::ShaderReflection;
 use crate::texture::Texture;
-use crate::vertex::PosTexCoordNormalVertex;
+use crate::vertex::VertexAttribute;
 
 use super::apply_material::ApplyMaterial;
+use super::sky::SkyParams;
+use super::uniforms::Uniform;
+
+// Exponential height fog, denser below `height` and thinner above it
+#[derive(Clone, Copy)]
+pub struct Fog {
+    // Extinction per unit of distance at `height`, 0 disables fog
+    pub density: f32,
+    // How quickly the density decreases with height, 0 for uniform fog
+    pub height_falloff: f32,
+    pub height: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        // Mostly hides geometry at the far plane of the camera
+        Self {
+            density: 0.03,
+            height_falloff: 0.05,
+            height: 0.0,
+        }
+    }
+}
+
+#[derive(ShaderType)]
+struct FogUniform {
+    inv_view_proj: Mat4,
+    camera_position: Vec3,
+    density: f32,
+    height_falloff: f32,
+    height: f32,
+    procedural_sky: u32,
+}
 
 pub struct PostProcessMaterial {
     pipeline: RenderPipeline,
+    shader: ShaderHandle,
+    bind_group_layouts: [BindGroupLayout; 3],
     texture_bind_group: BindGroup,
+    fog_uniform: Uniform<FogUniform>,
+    sky_uniform: Uniform<SkyParams>,
 }
 
 impl PostProcessMaterial {
-    pub fn new(gfx: &Graphics, assets: &Assets, texture: &Texture) -> Self {
-        let (texture_bind_group_layout, texture_bind_group) =
-            gfx.new_texture_bind_group(texture, wgpu::TextureViewDimension::D2);
+    const TEXTURE_GROUP: u32 = 0;
+    const FOG_GROUP: u32 = 1;
+    const SKY_GROUP: u32 = 2;
+    pub const VERTEX_ATTRIBUTES: &'static [VertexAttribute] =
+        &[VertexAttribute::Position, VertexAttribute::TexCoords];
 
-        let pipeline = gfx.new_render_pipeline(RenderPipelineParams {
-            shader_module: assets.shader(assets.postprocess_shader),
-            depth_write: true,
-            depth_enabled: true,
-            bind_group_layouts: &[&texture_bind_group_layout],
-            vertex_buffer_layouts: &[PosTexCoordNormalVertex::buffer_layout()],
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_vertex_inputs(Self::VERTEX_ATTRIBUTES)?;
+        let d2 = wgpu::TextureViewDimension::D2;
+        reflection.check_texture(Self::TEXTURE_GROUP, 0, d2)?;
+        reflection.check_sampler(Self::TEXTURE_GROUP, 1)?;
+        reflection.check_texture(Self::TEXTURE_GROUP, 2, d2)?;
+        reflection.check_texture(Self::TEXTURE_GROUP, 3, wgpu::TextureViewDimension::Cube)?;
+        reflection.check_sampler(Self::TEXTURE_GROUP, 4)?;
+        reflection.check_uniform::<FogUniform>(Self::FOG_GROUP, 0)?;
+        reflection.check_uniform::<SkyParams>(Self::SKY_GROUP, 0)
+    }
+
+    // The skybox is sampled for the color of the fog
+    pub fn check_skybox_texture(gfx: &Graphics, assets: &Assets, skybox: &Texture) -> Result<()> {
+        assets
+            .shader_reflection(assets.postprocess_shader)
+            .check_texture_format(Self::TEXTURE_GROUP, 3, skybox.format(), gfx.features())
+    }
+
+    // Applies fog to the color of the target using its depth, blending towards the sky
+    pub fn new(gfx: &Graphics, assets: &Assets, source: &RenderTarget, skybox: &Texture) -> Self {
+        let shader = assets.postprocess_shader;
+        let reflection = assets.shader_reflection(shader);
+        let bind_group_layouts = [
+            reflection.bind_group_layout(gfx, Self::TEXTURE_GROUP),
+            reflection.bind_group_layout(gfx, Self::FOG_GROUP),
+            reflection.bind_group_layout(gfx, Self::SKY_GROUP),
+        ];
+
+        let color_tex = source.color_tex();
+        let texture_bind_group = gfx.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layouts[Self::TEXTURE_GROUP as usize],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(color_tex.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(color_tex.sampler()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(source.depth_tex().view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(skybox.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(skybox.sampler()),
+                },
+            ],
         });
 
+        let fog_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::FOG_GROUP as usize],
+            &FogUniform {
+                inv_view_proj: Mat4::identity(),
+                camera_position: Vec3::zeros(),
+                density: 0.0,
+                height_falloff: 0.0,
+                height: 0.0,
+                procedural_sky: 0,
+            },
+        );
+        let sky_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::SKY_GROUP as usize],
+            &SkyParams::default(),
+        );
+
+        let pipeline = Self::new_pipeline(gfx, assets.shader(shader), &bind_group_layouts);
+
         Self {
             pipeline,
+            shader,
+            bind_group_layouts,
             texture_bind_group,
+            fog_uniform,
+            sky_uniform,
+        }
+    }
+
+    pub fn shader(&self) -> ShaderHandle {
+        self.shader
+    }
+
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+    ) -> RenderPipeline {
+        Self::new_pipeline(gfx, shader_module, &self.bind_group_layouts)
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn new_pipeline(
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+        bind_group_layouts: &[BindGroupLayout; 3],
+    ) -> RenderPipeline {
+        gfx.new_render_pipeline(RenderPipelineParams {
+            shader_module,
+            depth_write: true,
+            depth_enabled: true,
+            bind_group_layouts: &[
+                &bind_group_layouts[0],
+                &bind_group_layouts[1],
+                &bind_group_layouts[2],
+            ],
+            vertex_buffer_layouts: &VertexAttribute::buffer_layouts(Self::VERTEX_ATTRIBUTES),
+        })
+    }
+
+    // `camera` is the one that rendered the source target. The fog takes the color of the
+    // procedural sky if its parameters are given, of the skybox otherwise.
+    pub fn set_fog(
+        &mut self,
+        gfx: &Graphics,
+        camera: &Camera,
+        camera_transform: &Transform,
+        fog: &Fog,
+        sky: Option<&SkyParams>,
+    ) {
+        let view_proj =
+            OPENGL_TO_WGPU_MATRIX * camera.proj_matrix() * camera_transform.view_matrix();
+        self.fog_uniform.write(
+            gfx,
+            &FogUniform {
+                inv_view_proj: view_proj.try_inverse().unwrap(),
+                camera_position: camera_transform.position(),
+                density: fog.density,
+                height_falloff: fog.height_falloff,
+                height: fog.height,
+                procedural_sky: sky.is_some() as u32,
+            },
+        );
+        if let Some(sky) = sky {
+            self.sky_uniform.write(gfx, sky);
         }
     }
 }
@@ -35,6 +215,8 @@ impl PostProcessMaterial {
 impl ApplyMaterial for PostProcessMaterial {
     fn apply<'a>(&'a self, encoder: &mut wgpu::RenderBundleEncoder<'a>) {
         encoder.set_pipeline(&self.pipeline);
-        encoder.set_bind_group(0, &self.texture_bind_group, &[]);
+        encoder.set_bind_group(Self::TEXTURE_GROUP, &self.texture_bind_group, &[]);
+        encoder.set_bind_group(Self::FOG_GROUP, self.fog_uniform.bind_group(), &[]);
+        encoder.set_bind_group(Self::SKY_GROUP, self.sky_uniform.bind_group(), &[]);
     }
 }
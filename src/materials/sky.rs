@@ -0,0 +1,158 @@
+use anyhow::Result;
+use encase::ShaderType;
+
+use crate::assets::{Assets, ShaderHandle};
+use crate::components::{Camera, Transform};
+use crate::graphics::{Graphics, RenderPipelineParams};
+use crate::math::Vec3;
+use crate::shader_reflection::ShaderReflection;
+use crate::vertex::VertexAttribute;
+
+use super::apply_material::ApplyMaterial;
+use super::uniforms::{Uniform, ViewInvProjUniform};
+
+#[derive(ShaderType, Clone, Copy, PartialEq)]
+pub struct SkyParams {
+    // Towards the sun, normalized
+    pub sun_direction: Vec3,
+    // Haziness of the air from 1 (clear) to around 10 (hazy)
+    pub turbidity: f32,
+    pub ground_color: Vec3,
+    // Towards the moon, normalized
+    pub moon_direction: Vec3,
+    // Added to the scattered sunlight, visible once the sun has set
+    pub night_color: Vec3,
+}
+
+impl Default for SkyParams {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::new(0.5, 0.4, 0.3).normalize(),
+            turbidity: 2.0,
+            ground_color: Vec3::new(0.3, 0.27, 0.22),
+            moon_direction: -Vec3::new(0.5, 0.4, 0.3).normalize(),
+            night_color: Vec3::zeros(),
+        }
+    }
+}
+
+// Alternative to `SkyboxMaterial` computing the sky color from atmospheric scattering of sunlight
+pub struct SkyMaterial {
+    pipeline: wgpu::RenderPipeline,
+    shader: ShaderHandle,
+    bind_group_layouts: [wgpu::BindGroupLayout; 2],
+    matrices_uniform: Uniform<ViewInvProjUniform>,
+    params_uniform: Uniform<SkyParams>,
+    params: SkyParams,
+}
+
+impl SkyMaterial {
+    const MATRICES_GROUP: u32 = 0;
+    const PARAMS_GROUP: u32 = 1;
+    pub const VERTEX_ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute::Position];
+
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_vertex_inputs(Self::VERTEX_ATTRIBUTES)?;
+        reflection.check_uniform::<ViewInvProjUniform>(Self::MATRICES_GROUP, 0)?;
+        reflection.check_uniform::<SkyParams>(Self::PARAMS_GROUP, 0)
+    }
+
+    pub fn new(gfx: &Graphics, assets: &Assets) -> Self {
+        let shader = assets.sky_shader;
+        let reflection = assets.shader_reflection(shader);
+        let bind_group_layouts = [
+            reflection.bind_group_layout(gfx, Self::MATRICES_GROUP),
+            reflection.bind_group_layout(gfx, Self::PARAMS_GROUP),
+        ];
+
+        let matrices_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::MATRICES_GROUP as usize],
+            &ViewInvProjUniform::default(),
+        );
+        let params = SkyParams::default();
+        let params_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::PARAMS_GROUP as usize],
+            &params,
+        );
+
+        let pipeline = Self::new_pipeline(gfx, assets.shader(shader), &bind_group_layouts);
+
+        Self {
+            pipeline,
+            shader,
+            bind_group_layouts,
+            matrices_uniform,
+            params_uniform,
+            params,
+        }
+    }
+}
+
+impl SkyMaterial {
+    pub fn shader(&self) -> ShaderHandle {
+        self.shader
+    }
+
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        Self::new_pipeline(gfx, shader_module, &self.bind_group_layouts)
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn new_pipeline(
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+        bind_group_layouts: &[wgpu::BindGroupLayout; 2],
+    ) -> wgpu::RenderPipeline {
+        gfx.new_render_pipeline(RenderPipelineParams {
+            shader_module,
+            depth_write: false,
+            depth_enabled: true,
+            bind_group_layouts: &[&bind_group_layouts[0], &bind_group_layouts[1]],
+            vertex_buffer_layouts: &VertexAttribute::buffer_layouts(Self::VERTEX_ATTRIBUTES),
+        })
+    }
+
+    pub fn params(&self) -> SkyParams {
+        self.params
+    }
+
+    pub fn set_params(&mut self, gfx: &Graphics, params: SkyParams) {
+        let params = SkyParams {
+            sun_direction: params.sun_direction.normalize(),
+            moon_direction: params.moon_direction.normalize(),
+            ..params
+        };
+        if params != self.params {
+            self.params = params;
+            self.params_uniform.write(gfx, &params);
+        }
+    }
+
+    pub fn set_wvp(&mut self, gfx: &Graphics, camera: &Camera, camera_transform: &Transform) {
+        self.matrices_uniform.write(
+            gfx,
+            &ViewInvProjUniform::new(&camera_transform.view_matrix(), &camera.proj_matrix()),
+        );
+    }
+}
+
+impl ApplyMaterial for SkyMaterial {
+    fn apply<'a>(&'a self, encoder: &mut wgpu::RenderBundleEncoder<'a>) {
+        encoder.set_pipeline(&self.pipeline);
+        encoder.set_bind_group(
+            Self::MATRICES_GROUP,
+            self.matrices_uniform.bind_group(),
+            &[],
+        );
+        encoder.set_bind_group(Self::PARAMS_GROUP, self.params_uniform.bind_group(), &[]);
+    }
+}
@@ -1,58 +1,105 @@
-use crate::assets::Assets;
+use anyhow::Result;
+
+use crate::assets::{Assets, ShaderHandle};
 use crate::components::{Camera, Transform};
 use crate::graphics::{Graphics, RenderPipelineParams};
+use crate::shader_reflection::ShaderReflection;
 use crate::texture::Texture;
-use crate::vertex::PosTexCoordNormalVertex;
+use crate::vertex::VertexAttribute;
 
 use super::apply_material::ApplyMaterial;
-use super::uniforms::ViewInvProjUniform;
+use super::uniforms::{Uniform, ViewInvProjUniform};
 
 pub struct SkyboxMaterial {
     pipeline: wgpu::RenderPipeline,
+    shader: ShaderHandle,
+    bind_group_layouts: [wgpu::BindGroupLayout; 2],
     texture_bind_group: wgpu::BindGroup,
-    matrices_uniform: ViewInvProjUniform,
-    matrices_uniform_buf: wgpu::Buffer,
-    matrices_uniform_bind_group: wgpu::BindGroup,
+    matrices_uniform: Uniform<ViewInvProjUniform>,
 }
 
 impl SkyboxMaterial {
+    const MATRICES_GROUP: u32 = 0;
+    const TEXTURE_GROUP: u32 = 1;
+    pub const VERTEX_ATTRIBUTES: &'static [VertexAttribute] = &[VertexAttribute::Position];
+
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_vertex_inputs(Self::VERTEX_ATTRIBUTES)?;
+        reflection.check_uniform::<ViewInvProjUniform>(Self::MATRICES_GROUP, 0)?;
+        reflection.check_texture(Self::TEXTURE_GROUP, 0, wgpu::TextureViewDimension::Cube)?;
+        reflection.check_sampler(Self::TEXTURE_GROUP, 1)
+    }
+
+    pub fn check_texture(gfx: &Graphics, assets: &Assets, texture: &Texture) -> Result<()> {
+        assets
+            .shader_reflection(assets.skybox_shader)
+            .check_texture_format(Self::TEXTURE_GROUP, 0, texture.format(), gfx.features())
+    }
+
     pub fn new(gfx: &Graphics, assets: &Assets, texture: &Texture) -> Self {
-        let matrices_uniform = ViewInvProjUniform::default();
-        let (matrices_uniform_bind_group_layout, matrices_uniform_bind_group, matrices_uniform_buf) =
-            gfx.new_uniform_bind_group(bytemuck::cast_slice(&[matrices_uniform]));
+        let shader = assets.skybox_shader;
+        let reflection = assets.shader_reflection(shader);
+        let bind_group_layouts = [
+            reflection.bind_group_layout(gfx, Self::MATRICES_GROUP),
+            reflection.bind_group_layout(gfx, Self::TEXTURE_GROUP),
+        ];
 
-        let (texture_bind_group_layout, texture_bind_group) =
-            gfx.new_texture_bind_group(texture, wgpu::TextureViewDimension::Cube);
+        let matrices_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::MATRICES_GROUP as usize],
+            &ViewInvProjUniform::default(),
+        );
 
-        let pipeline = gfx.new_render_pipeline(RenderPipelineParams {
-            shader_module: assets.shader(assets.skybox_shader),
-            depth_write: false,
-            depth_enabled: true,
-            bind_group_layouts: &[
-                &matrices_uniform_bind_group_layout,
-                &texture_bind_group_layout,
-            ],
-            vertex_buffer_layouts: &[PosTexCoordNormalVertex::buffer_layout()],
-        });
+        let texture_bind_group =
+            gfx.new_texture_bind_group(&bind_group_layouts[Self::TEXTURE_GROUP as usize], texture);
+
+        let pipeline = Self::new_pipeline(gfx, assets.shader(shader), &bind_group_layouts);
 
         Self {
             pipeline,
+            shader,
+            bind_group_layouts,
             texture_bind_group,
             matrices_uniform,
-            matrices_uniform_buf,
-            matrices_uniform_bind_group,
         }
     }
 }
 
 impl SkyboxMaterial {
+    pub fn shader(&self) -> ShaderHandle {
+        self.shader
+    }
+
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        Self::new_pipeline(gfx, shader_module, &self.bind_group_layouts)
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn new_pipeline(
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+        bind_group_layouts: &[wgpu::BindGroupLayout; 2],
+    ) -> wgpu::RenderPipeline {
+        gfx.new_render_pipeline(RenderPipelineParams {
+            shader_module,
+            depth_write: false,
+            depth_enabled: true,
+            bind_group_layouts: &[&bind_group_layouts[0], &bind_group_layouts[1]],
+            vertex_buffer_layouts: &VertexAttribute::buffer_layouts(Self::VERTEX_ATTRIBUTES),
+        })
+    }
+
     pub fn set_wvp(&mut self, gfx: &Graphics, camera: &Camera, camera_transform: &Transform) {
-        self.matrices_uniform
-            .update(&camera_transform.view_matrix(), &camera.proj_matrix());
-        gfx.queue().write_buffer(
-            &self.matrices_uniform_buf,
-            0,
-            bytemuck::cast_slice(&[self.matrices_uniform]),
+        self.matrices_uniform.write(
+            gfx,
+            &ViewInvProjUniform::new(&camera_transform.view_matrix(), &camera.proj_matrix()),
         );
     }
 }
@@ -60,7 +107,11 @@ impl SkyboxMaterial {
 impl ApplyMaterial for SkyboxMaterial {
     fn apply<'a>(&'a self, encoder: &mut wgpu::RenderBundleEncoder<'a>) {
         encoder.set_pipeline(&self.pipeline);
-        encoder.set_bind_group(0, &self.matrices_uniform_bind_group, &[]);
-        encoder.set_bind_group(1, &self.texture_bind_group, &[]);
+        encoder.set_bind_group(
+            Self::MATRICES_GROUP,
+            self.matrices_uniform.bind_group(),
+            &[],
+        );
+        encoder.set_bind_group(Self::TEXTURE_GROUP, &self.texture_bind_group, &[]);
     }
 }
@@ -1,51 +1,240 @@
-use crate::assets::Assets;
+use anyhow::Result;
+
+use crate::assets::{Assets, ShaderHandle};
 use crate::components::{Camera, Transform};
 use crate::graphics::{Graphics, RenderPipelineParams};
+use crate::ibl::Lighting;
+use crate::math::{Mat4, Vec2};
+use crate::shader_reflection::ShaderReflection;
 use crate::texture::Texture;
-use crate::vertex::PosTexCoordNormalVertex;
+use crate::vertex::VertexAttribute;
 
 use super::apply_material::ApplyMaterial;
-use super::uniforms::WorldViewProjUniform;
+use super::uniforms::{SkinnedWorldViewProjUniform, Uniform, WorldViewProjUniform};
 
 pub struct TexturedMaterial {
     pipeline: wgpu::RenderPipeline,
+    shader: ShaderHandle,
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
     texture_bind_group: wgpu::BindGroup,
-    matrices_uniform: WorldViewProjUniform,
-    matrices_uniform_buf: wgpu::Buffer,
-    matrices_uniform_bind_group: wgpu::BindGroup,
+    // Only used by the lit variant
+    environment_bind_group: Option<wgpu::BindGroup>,
+    matrices_uniform: MatricesUniform,
+    // Only used by the skinned variant, uploaded along with the other matrices
+    joint_matrices: Vec<Mat4>,
+    uv_tiling_uniform: Uniform<Vec2>,
+}
+
+enum MatricesUniform {
+    Rigid(Uniform<WorldViewProjUniform>),
+    Skinned(Uniform<SkinnedWorldViewProjUniform>),
 }
 
 impl TexturedMaterial {
-    pub fn new(gfx: &Graphics, assets: &Assets, texture: &Texture) -> Self {
-        let matrices_uniform = WorldViewProjUniform::default();
-        let (matrices_uniform_bind_group_layout, matrices_uniform_bind_group, matrices_uniform_buf) =
-            gfx.new_uniform_bind_group(bytemuck::cast_slice(&[matrices_uniform]));
+    const TEXTURE_GROUP: u32 = 0;
+    const MATRICES_GROUP: u32 = 1;
+    const UV_TILING_GROUP: u32 = 2;
+    const ENVIRONMENT_GROUP: u32 = 3;
+    pub const VERTEX_ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::Position,
+        VertexAttribute::TexCoords,
+        VertexAttribute::Normal,
+    ];
+    pub const SKINNED_VERTEX_ATTRIBUTES: &'static [VertexAttribute] = &[
+        VertexAttribute::Position,
+        VertexAttribute::TexCoords,
+        VertexAttribute::Normal,
+        VertexAttribute::Joints,
+        VertexAttribute::Weights,
+    ];
 
-        let (texture_bind_group_layout, texture_bind_group) =
-            gfx.new_texture_bind_group(texture, wgpu::TextureViewDimension::D2);
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_vertex_inputs(Self::VERTEX_ATTRIBUTES)?;
+        reflection.check_uniform::<WorldViewProjUniform>(Self::MATRICES_GROUP, 0)?;
+        Self::check_common_bindings(reflection)
+    }
 
-        let pipeline = gfx.new_render_pipeline(RenderPipelineParams {
-            shader_module: assets.shader(assets.textured_shader),
-            depth_write: true,
-            depth_enabled: true,
-            bind_group_layouts: &[
-                &texture_bind_group_layout,
-                &matrices_uniform_bind_group_layout,
-            ],
-            vertex_buffer_layouts: &[PosTexCoordNormalVertex::buffer_layout()],
+    pub fn check_lit_shader(reflection: &ShaderReflection) -> Result<()> {
+        Self::check_shader(reflection)?;
+        Self::check_environment_bindings(reflection)
+    }
+
+    pub fn check_skinned_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_vertex_inputs(Self::SKINNED_VERTEX_ATTRIBUTES)?;
+        reflection.check_uniform::<SkinnedWorldViewProjUniform>(Self::MATRICES_GROUP, 0)?;
+        Self::check_common_bindings(reflection)?;
+        Self::check_environment_bindings(reflection)
+    }
+
+    fn check_common_bindings(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_texture(Self::TEXTURE_GROUP, 0, wgpu::TextureViewDimension::D2)?;
+        reflection.check_sampler(Self::TEXTURE_GROUP, 1)?;
+        reflection.check_uniform::<Vec2>(Self::UV_TILING_GROUP, 0)
+    }
+
+    fn check_environment_bindings(reflection: &ShaderReflection) -> Result<()> {
+        let cube = wgpu::TextureViewDimension::Cube;
+        reflection.check_texture(Self::ENVIRONMENT_GROUP, 0, cube)?;
+        reflection.check_texture(Self::ENVIRONMENT_GROUP, 1, cube)?;
+        reflection.check_texture(Self::ENVIRONMENT_GROUP, 2, wgpu::TextureViewDimension::D2)?;
+        reflection.check_sampler(Self::ENVIRONMENT_GROUP, 3)?;
+        reflection.check_uniform::<Lighting>(Self::ENVIRONMENT_GROUP, 4)
+    }
+
+    // Checks that the texture can be bound to every variant, before any material is created
+    // with it
+    pub fn check_texture(gfx: &Graphics, assets: &Assets, texture: &Texture) -> Result<()> {
+        let shaders = [
+            assets.textured_shader,
+            assets.textured_lit_shader,
+            assets.textured_skinned_shader,
+        ];
+        for shader in shaders {
+            assets.shader_reflection(shader).check_texture_format(
+                Self::TEXTURE_GROUP,
+                0,
+                texture.format(),
+                gfx.features(),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn new(gfx: &Graphics, assets: &Assets, texture: &Texture, lit: bool) -> Self {
+        Self::new_variant(gfx, assets, texture, lit, false)
+    }
+
+    // Lit, for meshes with joints and weights. The joint matrices are set every frame from
+    // the `Skin` of the entity.
+    pub fn new_skinned(gfx: &Graphics, assets: &Assets, texture: &Texture) -> Self {
+        Self::new_variant(gfx, assets, texture, true, true)
+    }
+
+    fn new_variant(
+        gfx: &Graphics,
+        assets: &Assets,
+        texture: &Texture,
+        lit: bool,
+        skinned: bool,
+    ) -> Self {
+        let shader = match (lit, skinned) {
+            (_, true) => assets.textured_skinned_shader,
+            (true, false) => assets.textured_lit_shader,
+            (false, false) => assets.textured_shader,
+        };
+        let reflection = assets.shader_reflection(shader);
+        let group_count = if lit {
+            Self::ENVIRONMENT_GROUP + 1
+        } else {
+            Self::UV_TILING_GROUP + 1
+        };
+        let bind_group_layouts = (0..group_count)
+            .map(|group| reflection.bind_group_layout(gfx, group))
+            .collect::<Vec<_>>();
+
+        let texture_bind_group =
+            gfx.new_texture_bind_group(&bind_group_layouts[Self::TEXTURE_GROUP as usize], texture);
+
+        let environment_bind_group = lit.then(|| {
+            assets
+                .environment
+                .new_bind_group(gfx, &bind_group_layouts[Self::ENVIRONMENT_GROUP as usize])
         });
 
+        let matrices_layout = &bind_group_layouts[Self::MATRICES_GROUP as usize];
+        let matrices_uniform = if skinned {
+            MatricesUniform::Skinned(Uniform::new(
+                gfx,
+                matrices_layout,
+                &SkinnedWorldViewProjUniform::default(),
+            ))
+        } else {
+            MatricesUniform::Rigid(Uniform::new(
+                gfx,
+                matrices_layout,
+                &WorldViewProjUniform::default(),
+            ))
+        };
+        let uv_tiling_uniform = Uniform::new(
+            gfx,
+            &bind_group_layouts[Self::UV_TILING_GROUP as usize],
+            &Vec2::new(1.0, 1.0),
+        );
+
+        let vertex_attributes = if skinned {
+            Self::SKINNED_VERTEX_ATTRIBUTES
+        } else {
+            Self::VERTEX_ATTRIBUTES
+        };
+        let pipeline = Self::new_pipeline(
+            gfx,
+            assets.shader(shader),
+            &bind_group_layouts,
+            vertex_attributes,
+        );
+
         Self {
+            shader,
+            bind_group_layouts,
             texture_bind_group,
+            environment_bind_group,
             matrices_uniform,
-            matrices_uniform_buf,
-            matrices_uniform_bind_group,
+            joint_matrices: Vec::new(),
+            uv_tiling_uniform,
             pipeline,
         }
     }
 }
 
 impl TexturedMaterial {
+    pub fn shader(&self) -> ShaderHandle {
+        self.shader
+    }
+
+    pub fn vertex_attributes(&self) -> &'static [VertexAttribute] {
+        match self.matrices_uniform {
+            MatricesUniform::Rigid(_) => Self::VERTEX_ATTRIBUTES,
+            MatricesUniform::Skinned(_) => Self::SKINNED_VERTEX_ATTRIBUTES,
+        }
+    }
+
+    pub fn rebuild_pipeline(
+        &self,
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        Self::new_pipeline(
+            gfx,
+            shader_module,
+            &self.bind_group_layouts,
+            self.vertex_attributes(),
+        )
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: wgpu::RenderPipeline) {
+        self.pipeline = pipeline;
+    }
+
+    fn new_pipeline(
+        gfx: &Graphics,
+        shader_module: &wgpu::ShaderModule,
+        bind_group_layouts: &[wgpu::BindGroupLayout],
+        vertex_attributes: &[VertexAttribute],
+    ) -> wgpu::RenderPipeline {
+        gfx.new_render_pipeline(RenderPipelineParams {
+            shader_module,
+            depth_write: true,
+            depth_enabled: true,
+            bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+            vertex_buffer_layouts: &VertexAttribute::buffer_layouts(vertex_attributes),
+        })
+    }
+
+    // How many times the texture repeats across the UV range of the mesh
+    pub fn set_uv_tiling(&mut self, gfx: &Graphics, tiling: Vec2) {
+        self.uv_tiling_uniform.write(gfx, &tiling);
+    }
+
     pub fn set_wvp(
         &mut self,
         gfx: &Graphics,
@@ -53,23 +242,42 @@ impl TexturedMaterial {
         camera_transform: &Transform,
         transform: &Transform,
     ) {
-        self.matrices_uniform.update(
+        let matrices = WorldViewProjUniform::new(
             &transform.matrix(),
             &camera_transform.view_matrix(),
             &camera.proj_matrix(),
         );
-        gfx.queue().write_buffer(
-            &self.matrices_uniform_buf,
-            0,
-            bytemuck::cast_slice(&[self.matrices_uniform]),
-        );
+        match &mut self.matrices_uniform {
+            MatricesUniform::Rigid(u) => u.write(gfx, &matrices),
+            MatricesUniform::Skinned(u) => u.write(
+                gfx,
+                &SkinnedWorldViewProjUniform::new(matrices, &self.joint_matrices),
+            ),
+        }
+    }
+
+    // Joint transforms relative to the world matrix, only used by the skinned variant
+    pub fn set_joint_matrices(&mut self, joints: Vec<Mat4>) {
+        self.joint_matrices = joints;
     }
 }
 
 impl ApplyMaterial for TexturedMaterial {
     fn apply<'a>(&'a self, encoder: &mut wgpu::RenderBundleEncoder<'a>) {
         encoder.set_pipeline(&self.pipeline);
-        encoder.set_bind_group(0, &self.texture_bind_group, &[]);
-        encoder.set_bind_group(1, &self.matrices_uniform_bind_group, &[]);
+        encoder.set_bind_group(Self::TEXTURE_GROUP, &self.texture_bind_group, &[]);
+        let matrices_bind_group = match &self.matrices_uniform {
+            MatricesUniform::Rigid(u) => u.bind_group(),
+            MatricesUniform::Skinned(u) => u.bind_group(),
+        };
+        encoder.set_bind_group(Self::MATRICES_GROUP, matrices_bind_group, &[]);
+        encoder.set_bind_group(
+            Self::UV_TILING_GROUP,
+            self.uv_tiling_uniform.bind_group(),
+            &[],
+        );
+        if let Some(bind_group) = &self.environment_bind_group {
+            encoder.set_bind_group(Self::ENVIRONMENT_GROUP, bind_group, &[]);
+        }
     }
 }
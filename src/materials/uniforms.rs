@@ -1,64 +1,160 @@
-use crate::math::{Mat4, OPENGL_TO_WGPU_MATRIX, Vec3};
+use std::marker::PhantomData;
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vec3Uniform([f32; 3]);
+use encase::internal::WriteInto;
+use encase::ShaderType;
+use wgpu::util::DeviceExt;
 
-impl Vec3Uniform {
-    pub fn update(&mut self, value: Vec3) {
-        self.0 = [value.x, value.y, value.z];
+use crate::graphics::Graphics;
+use crate::math::{Mat3, Mat4, Vec3, OPENGL_TO_WGPU_MATRIX};
+
+// Uniform buffer holding a single value, for bind groups with other bindings. Values are laid out
+// following WGSL alignment rules by `encase`, so types like `Vec3` and `Mat3` get padded as the
+// shader expects.
+pub struct UniformBuffer<T> {
+    buffer: wgpu::Buffer,
+    // Encoded last value, reused so that writes don't allocate
+    data: encase::UniformBuffer<Vec<u8>>,
+    value_type: PhantomData<T>,
+}
+
+impl<T: ShaderType + WriteInto> UniformBuffer<T> {
+    pub fn new(gfx: &Graphics, value: &T) -> Self {
+        let mut data = encase::UniformBuffer::new(Vec::new());
+        Self::encode(&mut data, value);
+        let buffer = gfx.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: data.as_ref(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            buffer,
+            data,
+            value_type: PhantomData,
+        }
+    }
+
+    pub fn write(&mut self, gfx: &Graphics, value: &T) {
+        Self::encode(&mut self.data, value);
+        gfx.queue()
+            .write_buffer(&self.buffer, 0, self.data.as_ref());
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    fn encode(data: &mut encase::UniformBuffer<Vec<u8>>, value: &T) {
+        // Can only fail when writing into a fixed size buffer
+        data.write(value).unwrap();
     }
 }
 
-impl Default for Vec3Uniform {
-    fn default() -> Self {
-        Self([0.0, 0.0, 1.0])
+// Uniform buffer with a bind group where it is the only binding
+pub struct Uniform<T> {
+    buffer: UniformBuffer<T>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl<T: ShaderType + WriteInto> Uniform<T> {
+    pub fn new(gfx: &Graphics, layout: &wgpu::BindGroupLayout, value: &T) -> Self {
+        let buffer = UniformBuffer::new(gfx, value);
+        let bind_group = gfx.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.buffer().as_entire_binding(),
+            }],
+        });
+        Self { buffer, bind_group }
+    }
+
+    pub fn write(&mut self, gfx: &Graphics, value: &T) {
+        self.buffer.write(gfx, value);
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(ShaderType)]
 pub struct WorldViewProjUniform {
-    world: [[f32; 4]; 4],
-    view_proj: [[f32; 4]; 4],
+    world: Mat4,
+    view_proj: Mat4,
+    camera_position: Vec3,
 }
 
 impl WorldViewProjUniform {
-    pub fn update(&mut self, world: &Mat4, view: &Mat4, proj: &Mat4) {
-        self.world = (*world).into();
-        self.view_proj = (OPENGL_TO_WGPU_MATRIX * proj * view).into();
+    pub fn new(world: &Mat4, view: &Mat4, proj: &Mat4) -> Self {
+        Self {
+            world: *world,
+            view_proj: OPENGL_TO_WGPU_MATRIX * proj * view,
+            camera_position: view.try_inverse().unwrap().column(3).xyz(),
+        }
     }
 }
 
 impl Default for WorldViewProjUniform {
     fn default() -> Self {
         Self {
-            world: Mat4::identity().into(),
-            view_proj: Mat4::identity().into(),
+            world: Mat4::identity(),
+            view_proj: Mat4::identity(),
+            camera_position: Vec3::zeros(),
         }
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+// Joints a skinned mesh can have
+pub const MAX_JOINTS: usize = 64;
+
+// Matches `Matrices` of shaders with SKINNED defined
+#[derive(ShaderType)]
+pub struct SkinnedWorldViewProjUniform {
+    matrices: WorldViewProjUniform,
+    // Relative to the world matrix. Unused ones are left as identity.
+    joints: [Mat4; MAX_JOINTS],
+}
+
+impl SkinnedWorldViewProjUniform {
+    pub fn new(matrices: WorldViewProjUniform, joints: &[Mat4]) -> Self {
+        let mut res = Self {
+            matrices,
+            joints: [Mat4::identity(); MAX_JOINTS],
+        };
+        let count = joints.len().min(MAX_JOINTS);
+        res.joints[..count].copy_from_slice(&joints[..count]);
+        res
+    }
+}
+
+impl Default for SkinnedWorldViewProjUniform {
+    fn default() -> Self {
+        Self::new(WorldViewProjUniform::default(), &[])
+    }
+}
+
+#[derive(ShaderType)]
 pub struct ViewInvProjUniform {
-    // Couldn't make it work with Matrix3, probably something to do with alignment and padding
-    view_mat: [[f32; 4]; 4],
-    proj_mat_inv: [[f32; 4]; 4],
+    // Rotation part of the view matrix, the skybox ignores camera position
+    view_rot: Mat3,
+    proj_inv: Mat4,
 }
 
 impl ViewInvProjUniform {
-    pub fn update(&mut self, view: &Mat4, proj: &Mat4) {
-        self.view_mat = (*view).into();
-        self.proj_mat_inv = (OPENGL_TO_WGPU_MATRIX * proj).try_inverse().unwrap().into();
+    pub fn new(view: &Mat4, proj: &Mat4) -> Self {
+        Self {
+            view_rot: view.fixed_view::<3, 3>(0, 0).into_owned(),
+            proj_inv: (OPENGL_TO_WGPU_MATRIX * proj).try_inverse().unwrap(),
+        }
     }
 }
 
 impl Default for ViewInvProjUniform {
     fn default() -> Self {
         Self {
-            view_mat: Mat4::identity().into(),
-            proj_mat_inv: Mat4::identity().into(),
+            view_rot: Mat3::identity(),
+            proj_inv: Mat4::identity(),
         }
     }
 }
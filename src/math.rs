@@ -6,6 +6,8 @@ use rapier3d::prelude::Real;
 
 pub type Vec2 = na::Vector2<f32>;
 pub type Vec3 = na::Vector3<f32>;
+pub type Vec4 = na::Vector4<f32>;
+pub type Mat3 = na::Matrix3<f32>;
 pub type Mat4 = na::Matrix4<f32>;
 pub type Quat = na::Quaternion<f32>;
 pub type UnitQuat = na::UnitQuaternion<f32>;
@@ -22,3 +24,19 @@ pub const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4::new(
 pub fn to_point3(v: Vec3) -> Point3<Real> {
     Point3::origin().add(v)
 }
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -1,27 +1,32 @@
-use std::io::{BufReader, Cursor};
+use std::collections::HashMap;
 
+use anyhow::*;
 use wgpu::util::DeviceExt;
 
-use crate::file;
-use crate::vertex::PosTexCoordNormalVertex;
+use crate::math::Vec3;
+use crate::vertex::{VertexAttribute, VertexLayout, Vertices};
 
 struct MeshPart {
-    vertex_buffer: wgpu::Buffer,
+    // One buffer per attribute of the mesh layout
+    vertex_buffers: Vec<(VertexAttribute, wgpu::Buffer)>,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
 }
 
 impl MeshPart {
-    fn from_buffers(
-        device: &wgpu::Device,
-        vertices: &[PosTexCoordNormalVertex],
-        indices: &[u32],
-    ) -> Self {
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+    fn from_buffers(device: &wgpu::Device, vertices: &Vertices, indices: &[u32]) -> Self {
+        let vertex_buffers = vertices
+            .layout()
+            .attributes()
+            .map(|a| {
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: vertices.attribute_data(a),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+                (a, buffer)
+            })
+            .collect();
 
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
@@ -30,115 +35,186 @@ impl MeshPart {
         });
 
         Self {
-            vertex_buffer,
+            vertex_buffers,
             index_buffer,
             num_indices: indices.len() as u32,
         }
     }
+}
+
+// Geometry kept on the CPU, for building colliders. All parts are merged together.
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
 
-    // TODO Use different vertex description and remove unused attributes
-    fn new_quad(device: &wgpu::Device) -> MeshPart {
-        let vertices = vec![
-            // Bottom left
-            PosTexCoordNormalVertex {
-                position: [-1.0, -1.0, 0.0],
-                tex_coords: [0.0, 0.0],
-                normal: [0.0, 0.0, 0.0], // unused
-            },
-            // Top left
-            PosTexCoordNormalVertex {
-                position: [-1.0, 1.0, 0.0],
-                tex_coords: [0.0, 1.0],
-                normal: [0.0, 0.0, 0.0], // unused
-            },
-            // Top right
-            PosTexCoordNormalVertex {
-                position: [1.0, 1.0, 0.0],
-                tex_coords: [1.0, 1.0],
-                normal: [0.0, 0.0, 0.0], // unused
-            },
-            // Bottom right
-            PosTexCoordNormalVertex {
-                position: [1.0, -1.0, 0.0],
-                tex_coords: [1.0, 0.0],
-                normal: [0.0, 0.0, 0.0], // unused
-            },
-        ];
-
-        let indices: Vec<u32> = vec![0, 1, 2, 0, 2, 3];
-
-        Self::from_buffers(device, &vertices, &indices)
+impl MeshData {
+    fn from_parts(parts: &[(Vertices, Vec<u32>)]) -> Self {
+        let mut data = Self {
+            positions: Vec::new(),
+            triangles: Vec::new(),
+        };
+        for (vertices, indices) in parts {
+            let first = data.positions.len() as u32;
+            data.positions
+                .extend(vertices.positions.iter().map(|&p| Vec3::from(p)));
+            data.triangles.extend(
+                indices
+                    .chunks_exact(3)
+                    .map(|t| [first + t[0], first + t[1], first + t[2]]),
+            );
+        }
+        data
+    }
+}
+
+// How normals missing from a file are generated
+#[derive(Clone, Copy, PartialEq)]
+pub enum Normals {
+    // Each triangle gets its own vertices, facing the same way
+    Flat,
+    // Averaged over the triangles around each position, weighted by their area
+    Smooth,
+}
+
+impl Normals {
+    pub fn generate(self, vertices: &mut Vertices, indices: &mut Vec<u32>) {
+        if self == Normals::Flat {
+            *vertices = vertices.gather(indices);
+            *indices = (0..vertices.len() as u32).collect();
+        }
+
+        let face_normal = |t: &[u32]| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(vertices.positions[t[i] as usize]));
+            (b - a).cross(&(c - a))
+        };
+        let normalize = |n: Vec3| n.try_normalize(1e-12).unwrap_or(Vec3::y()).into();
+
+        let mut normals = vec![[0.0; 3]; vertices.len()];
+        match self {
+            Normals::Flat => {
+                for t in indices.chunks_exact(3) {
+                    let normal = normalize(face_normal(t));
+                    for &i in t {
+                        normals[i as usize] = normal;
+                    }
+                }
+            }
+            Normals::Smooth => {
+                // Vertices split by texture seams still share the normal
+                let key = |i: u32| vertices.positions[i as usize].map(f32::to_bits);
+                let mut sums = HashMap::new();
+                for t in indices.chunks_exact(3) {
+                    let normal = face_normal(t);
+                    for &i in t {
+                        *sums.entry(key(i)).or_insert(Vec3::zeros()) += normal;
+                    }
+                }
+                for (i, normal) in normals.iter_mut().enumerate() {
+                    let sum = sums.get(&key(i as u32)).copied().unwrap_or(Vec3::zeros());
+                    *normal = normalize(sum);
+                }
+            }
+        }
+        vertices.normals = normals;
     }
 }
 
 pub struct Mesh {
     parts: Vec<MeshPart>,
+    // Attributes shared by all parts
+    layout: VertexLayout,
+    data: Option<MeshData>,
 }
 
 impl Mesh {
+    // Only has positions and texture coordinates
     pub fn new_quad(device: &wgpu::Device) -> Self {
-        Self {
-            parts: vec![MeshPart::new_quad(device)],
+        let vertices = Vertices {
+            // Bottom left, top left, top right, bottom right
+            positions: vec![
+                [-1.0, -1.0, 0.0],
+                [-1.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [1.0, -1.0, 0.0],
+            ],
+            tex_coords: vec![[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+            ..Default::default()
+        };
+        let indices = vec![0, 1, 2, 0, 2, 3];
+
+        Self::from_parts(device, &[(vertices, indices)], false).unwrap()
+    }
+
+    // Each part is drawn with its own vertex and index buffers, all parts must have the same
+    // attributes. The geometry is dropped once uploaded unless `keep_data` is set.
+    pub fn from_parts(
+        device: &wgpu::Device,
+        parts: &[(Vertices, Vec<u32>)],
+        keep_data: bool,
+    ) -> Result<Self> {
+        let layout = parts
+            .first()
+            .map_or(VertexLayout::default(), |(v, _)| v.layout());
+        for (i, (vertices, indices)) in parts.iter().enumerate() {
+            vertices
+                .check()
+                .with_context(|| format!("Invalid part {i}"))?;
+            if vertices.layout() != layout {
+                bail!(
+                    "Part {i} has attributes {:?} instead of {layout:?}",
+                    vertices.layout()
+                );
+            }
+            if let Some(&index) = indices
+                .iter()
+                .find(|&&index| index as usize >= vertices.len())
+            {
+                bail!("Index {index} out of range in part {i}");
+            }
         }
+
+        Ok(Self {
+            parts: parts
+                .iter()
+                .map(|(vertices, indices)| MeshPart::from_buffers(device, vertices, indices))
+                .collect(),
+            layout,
+            data: keep_data.then(|| MeshData::from_parts(parts)),
+        })
     }
 
-    pub async fn from_file(device: &wgpu::Device, file_name: &str) -> Mesh {
-        let text = file::read_string_asset(file_name).await.unwrap();
-        let cursor = Cursor::new(text);
-        let mut reader = BufReader::new(cursor);
-
-        let (meshes, _) = tobj::load_obj_buf_async(
-            &mut reader,
-            &tobj::LoadOptions {
-                triangulate: true,
-                single_index: true,
-                ..Default::default()
-            },
-            |p| async move {
-                let mat_text = file::read_string_asset(&p).await.unwrap();
-                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
-            },
-        )
-        .await
-        .unwrap();
-
-        let parts = meshes
-            .into_iter()
-            .map(|m| {
-                let vertices = (0..m.mesh.positions.len() / 3)
-                    .map(|i| PosTexCoordNormalVertex {
-                        position: [
-                            m.mesh.positions[i * 3],
-                            m.mesh.positions[i * 3 + 1],
-                            m.mesh.positions[i * 3 + 2],
-                        ],
-                        tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
-                        normal: [
-                            m.mesh.normals[i * 3],
-                            m.mesh.normals[i * 3 + 1],
-                            m.mesh.normals[i * 3 + 2],
-                        ],
-                    })
-                    .collect::<Vec<_>>();
-
-                MeshPart::from_buffers(device, &vertices, &m.mesh.indices)
-            })
-            .collect::<Vec<_>>();
+    pub fn layout(&self) -> VertexLayout {
+        self.layout
+    }
 
-        Mesh { parts }
+    // Only available if the mesh was created with `keep_data`
+    pub fn data(&self) -> Option<&MeshData> {
+        self.data.as_ref()
     }
 }
 
 pub trait DrawMesh<'a> {
-    fn draw_mesh(&mut self, mesh: &'a Mesh);
+    // Binds the attributes to the vertex buffer slots in their order, fails if the mesh doesn't
+    // have them all
+    fn draw_mesh(&mut self, mesh: &'a Mesh, attributes: &[VertexAttribute]) -> Result<()>;
 }
 
 impl<'a> DrawMesh<'a> for wgpu::RenderBundleEncoder<'a> {
-    fn draw_mesh(&mut self, mesh: &'a Mesh) {
+    fn draw_mesh(&mut self, mesh: &'a Mesh, attributes: &[VertexAttribute]) -> Result<()> {
+        mesh.layout.check(attributes)?;
         for part in &mesh.parts {
-            self.set_vertex_buffer(0, part.vertex_buffer.slice(..));
+            for (slot, &attribute) in attributes.iter().enumerate() {
+                let (_, buffer) = part
+                    .vertex_buffers
+                    .iter()
+                    .find(|&&(a, _)| a == attribute)
+                    .unwrap();
+                self.set_vertex_buffer(slot as u32, buffer.slice(..));
+            }
             self.set_index_buffer(part.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
             self.draw_indexed(0..part.num_indices, 0, 0..1);
         }
+        Ok(())
     }
 }
@@ -0,0 +1,162 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use anyhow::*;
+
+use crate::shader_reflection::ShaderReflection;
+
+// Fills mip levels of 2D textures by repeatedly downsampling the previous level on the GPU.
+// Textures need `RENDER_ATTACHMENT` usage and a renderable, filterable format.
+pub struct MipmapGenerator {
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    // Created on first use for each format
+    pipelines: RefCell<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+    pub fn check_shader(reflection: &ShaderReflection) -> Result<()> {
+        reflection.check_texture(0, 0, wgpu::TextureViewDimension::D2)?;
+        reflection.check_sampler(0, 1)
+    }
+
+    pub fn new(device: &wgpu::Device, reflection: &ShaderReflection) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = reflection.bind_group_layout(device, 0);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        Self {
+            sampler,
+            bind_group_layout,
+            pipeline_layout,
+            pipelines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Creates new pipelines from the given shader for the formats used so far without applying
+    // them
+    pub fn rebuild_pipelines(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> HashMap<wgpu::TextureFormat, wgpu::RenderPipeline> {
+        self.pipelines
+            .borrow()
+            .keys()
+            .map(|&format| (format, self.new_pipeline(device, shader, format)))
+            .collect()
+    }
+
+    pub fn set_pipelines(&mut self, pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>) {
+        self.pipelines = RefCell::new(pipelines);
+    }
+
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader: &wgpu::ShaderModule,
+        texture: &wgpu::Texture,
+    ) {
+        let format = texture.format();
+        let mut pipelines = self.pipelines.borrow_mut();
+        let pipeline = pipelines
+            .entry(format)
+            .or_insert_with(|| self.new_pipeline(device, shader, format));
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        for layer in 0..texture.depth_or_array_layers() {
+            let mip_view = |mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            };
+
+            for mip in 1..texture.mip_level_count() {
+                let src_view = mip_view(mip - 1);
+                let dst_view = mip_view(mip);
+
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&src_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &dst_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn new_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+}
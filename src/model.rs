@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::*;
+use hecs::{Entity, World};
+
+use crate::animation::{AnimationClip, Pose};
+use crate::assets::{Assets, MeshHandle, TextureHandle};
+use crate::components::{
+    AnimationPlayer, Material, Mesh, Name, Parent, RenderOrder, RenderTags, Skin, Transform,
+    RENDER_TAG_SCENE,
+};
+use crate::graphics::Graphics;
+use crate::materials::TexturedMaterial;
+use crate::math::{Mat4, Vec3};
+use crate::mesh::{self, Normals};
+use crate::texture::{SamplerOptions, Texture};
+use crate::{gltf, obj};
+
+// Meshes and textures added to the assets, with the hierarchy of nodes placing them. Can be
+// spawned any number of times.
+pub struct Model {
+    name: String,
+    nodes: Vec<ModelNode>,
+    // Indices into `nodes`
+    roots: Vec<usize>,
+    skins: Vec<ModelSkin>,
+    animations: Vec<Arc<AnimationClip>>,
+}
+
+pub struct ModelNode {
+    pub name: Option<String>,
+    pub transform: Transform,
+    // Mesh and color texture of each primitive
+    pub primitives: Vec<(MeshHandle, TextureHandle)>,
+    // Indices into the nodes of the model
+    pub children: Vec<usize>,
+    // Index into the skins of the model, deforming the primitives of the node
+    pub skin: Option<usize>,
+}
+
+pub struct ModelSkin {
+    // Indices into the nodes of the model
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+// Entities spawned for the nodes of a model
+struct SpawnedNodes {
+    // Indexed by node
+    entities: Vec<Entity>,
+    // Primitive entities with the skin deforming them
+    skinned: Vec<(Entity, usize)>,
+}
+
+impl Model {
+    pub fn new(
+        name: &str,
+        nodes: Vec<ModelNode>,
+        roots: Vec<usize>,
+        skins: Vec<ModelSkin>,
+        animations: Vec<Arc<AnimationClip>>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            nodes,
+            roots,
+            skins,
+            animations,
+        }
+    }
+
+    // Loads a glTF, GLB or OBJ file depending on its extension
+    pub async fn from_file(
+        gfx: &Graphics<'_>,
+        assets: &mut Assets,
+        file_name: &str,
+    ) -> Result<Self> {
+        let extension = Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gltf" | "glb") => gltf::load(gfx, assets, file_name).await,
+            Some("obj") => Self::from_obj(gfx, assets, file_name).await,
+            _ => bail!("Unsupported model format {file_name}"),
+        }
+    }
+
+    // A root node per object, with a primitive per material. Missing normals are smoothed and
+    // materials use their diffuse texture, or their diffuse color if they have none.
+    async fn from_obj(gfx: &Graphics<'_>, assets: &mut Assets, file_name: &str) -> Result<Self> {
+        let obj = obj::load(file_name, Normals::Smooth).await?;
+
+        let mut textures = HashMap::new();
+        let mut nodes = Vec::new();
+        for part in obj.parts {
+            let texture = match textures.get(&part.material) {
+                Some(&texture) => texture,
+                None => {
+                    let texture = match part.material.map(|m| &obj.materials[m]) {
+                        Some(m) => match &m.diffuse_texture {
+                            Some(path) => {
+                                let sampler = SamplerOptions {
+                                    address_mode: wgpu::AddressMode::Repeat,
+                                    anisotropy: 16,
+                                    ..Default::default()
+                                };
+                                Texture::new_2d_from_file(path, sampler, gfx, assets)
+                                    .await
+                                    .and_then(|texture| {
+                                        TexturedMaterial::check_texture(gfx, assets, &texture)?;
+                                        Ok(texture)
+                                    })
+                                    .with_context(|| format!("Invalid material {}", m.name))?
+                            }
+                            None => {
+                                let [r, g, b] = m.diffuse;
+                                Texture::new_2d_from_color(gfx, assets, [r, g, b, 1.0])?
+                            }
+                        },
+                        None => Texture::new_2d_from_color(gfx, assets, [1.0; 4])?,
+                    };
+                    let texture = assets.add_texture(texture);
+                    textures.insert(part.material, texture);
+                    texture
+                }
+            };
+
+            let mesh = mesh::Mesh::from_parts(gfx, &[(part.vertices, part.indices)], true)
+                .with_context(|| format!("Invalid part {}", part.name))?;
+            nodes.push(ModelNode {
+                name: Some(part.name),
+                transform: Transform::from_pos(Vec3::zeros()),
+                primitives: vec![(assets.add_mesh(mesh), texture)],
+                children: Vec::new(),
+                skin: None,
+            });
+        }
+
+        let roots = (0..nodes.len()).collect();
+        Ok(Self::new(file_name, nodes, roots, Vec::new(), Vec::new()))
+    }
+
+    // Spawns an entity for each node under a root entity at `transform`, and one for each
+    // primitive since entities have a single mesh and material. Returns the root, which gets an
+    // `AnimationPlayer` if the model has animations.
+    pub fn spawn(
+        &self,
+        gfx: &Graphics,
+        world: &mut World,
+        assets: &mut Assets,
+        transform: Transform,
+    ) -> Entity {
+        let root = world.spawn((transform, Name(self.name.clone())));
+        let mut spawned = SpawnedNodes {
+            entities: vec![Entity::DANGLING; self.nodes.len()],
+            skinned: Vec::new(),
+        };
+        for &node in &self.roots {
+            self.spawn_node(node, root, gfx, world, assets, &mut spawned);
+        }
+
+        // Joints can be anywhere in the hierarchy, so skins are added once all nodes exist
+        for (entity, skin) in spawned.skinned {
+            let skin = &self.skins[skin];
+            let joints = skin.joints.iter().map(|&j| spawned.entities[j]).collect();
+            let skin = Skin {
+                joints,
+                inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+            };
+            world.insert_one(entity, skin).unwrap();
+        }
+
+        if !self.animations.is_empty() {
+            let rest_poses = self
+                .nodes
+                .iter()
+                .map(|n| Pose::from_transform(&n.transform))
+                .collect();
+            let player = AnimationPlayer::new(&self.animations, spawned.entities, rest_poses);
+            world.insert_one(root, player).unwrap();
+        }
+
+        root
+    }
+
+    fn spawn_node(
+        &self,
+        index: usize,
+        parent: Entity,
+        gfx: &Graphics,
+        world: &mut World,
+        assets: &mut Assets,
+        spawned: &mut SpawnedNodes,
+    ) {
+        // A node reached twice would be its own ancestor or have several parents, which glTF
+        // forbids, and spawning it again could recurse forever
+        if spawned.entities[index] != Entity::DANGLING {
+            return;
+        }
+        let node = &self.nodes[index];
+        let entity = world.spawn((
+            node.transform,
+            Parent {
+                entity: parent,
+                local: node.transform,
+            },
+        ));
+        if let Some(name) = &node.name {
+            world.insert_one(entity, Name(name.clone())).unwrap();
+        }
+        spawned.entities[index] = entity;
+
+        let identity = Transform::from_pos(Vec3::zeros());
+        for &(mesh, texture) in &node.primitives {
+            // Primitives without joints and weights are left rigid
+            let skin = node.skin.filter(|_| {
+                let layout = assets.mesh(mesh).layout();
+                layout
+                    .check(TexturedMaterial::SKINNED_VERTEX_ATTRIBUTES)
+                    .is_ok()
+            });
+            let material = match skin {
+                Some(_) => assets.add_skinned_material(gfx, texture),
+                None => assets.add_textured_material(gfx, texture, true),
+            };
+            let primitive = world.spawn((
+                identity,
+                Parent {
+                    entity,
+                    local: identity,
+                },
+                Mesh(mesh),
+                Material(material),
+                RenderOrder(0),
+                RenderTags(RENDER_TAG_SCENE),
+            ));
+            if let Some(skin) = skin {
+                spawned.skinned.push((primitive, skin));
+            }
+        }
+
+        for &child in &node.children {
+            self.spawn_node(child, entity, gfx, world, assets, spawned);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+use anyhow::*;
+
+use crate::file;
+use crate::mesh::Normals;
+use crate::vertex::Vertices;
+
+// Geometry of an OBJ file with its MTL materials
+pub struct Obj {
+    pub parts: Vec<ObjPart>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+// Object or group of the file, split further when it uses several materials
+pub struct ObjPart {
+    pub name: String,
+    pub vertices: Vertices,
+    pub indices: Vec<u32>,
+    // Index into `Obj::materials`
+    pub material: Option<usize>,
+}
+
+pub struct ObjMaterial {
+    pub name: String,
+    // Linear color, used when there is no texture
+    pub diffuse: [f32; 3],
+    // Asset path of the diffuse texture
+    pub diffuse_texture: Option<String>,
+}
+
+// Parts missing normals get generated ones, missing texture coordinates are 0
+pub async fn load(file_name: &str, normals: Normals) -> Result<Obj> {
+    load_obj(file_name, normals)
+        .await
+        .with_context(|| format!("Failed to load {file_name}"))
+}
+
+async fn load_obj(file_name: &str, normals: Normals) -> Result<Obj> {
+    // Materials and textures are relative to the OBJ file
+    let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+    let asset_path = |p: &str| dir.join(p).to_string_lossy().replace('\\', "/");
+
+    let text = file::read_string_asset(file_name).await?;
+    let (models, materials) = tobj::load_obj_buf_async(
+        &mut BufReader::new(Cursor::new(text)),
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+        |p| {
+            let path = asset_path(&p);
+            async move {
+                let text = file::read_string_asset(&path).await.map_err(|e| {
+                    eprintln!("Failed to read {path}: {e}");
+                    tobj::LoadError::OpenFileFailed
+                })?;
+                tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(text)))
+            }
+        },
+    )
+    .await?;
+    // Parts are still loaded, with the default material
+    let materials = materials.unwrap_or_else(|e| {
+        eprintln!("Failed to load materials of {file_name}, using defaults: {e}");
+        Vec::new()
+    });
+
+    let parts = models
+        .into_iter()
+        .map(|m| {
+            let mesh = m.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+            if let Some(&i) = mesh.indices.iter().find(|&&i| i as usize >= vertex_count) {
+                bail!("Index {i} out of range in {}", m.name);
+            }
+            let has_normals = mesh.normals.len() == vertex_count * 3;
+            let has_tex_coords = mesh.texcoords.len() == vertex_count * 2;
+
+            let mut vertices = Vertices {
+                positions: mesh
+                    .positions
+                    .chunks_exact(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect(),
+                tex_coords: if has_tex_coords {
+                    mesh.texcoords
+                        .chunks_exact(2)
+                        .map(|t| [t[0], t[1]])
+                        .collect()
+                } else {
+                    vec![[0.0, 0.0]; vertex_count]
+                },
+                ..Default::default()
+            };
+            if has_normals {
+                vertices.normals = mesh
+                    .normals
+                    .chunks_exact(3)
+                    .map(|n| [n[0], n[1], n[2]])
+                    .collect();
+            }
+            let mut indices = mesh.indices;
+            if !has_normals {
+                normals.generate(&mut vertices, &mut indices);
+            }
+
+            Ok(ObjPart {
+                name: m.name,
+                vertices,
+                indices,
+                material: mesh.material_id.filter(|&i| i < materials.len()),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let materials = materials
+        .into_iter()
+        .map(|m| ObjMaterial {
+            name: m.name,
+            diffuse: m.diffuse.unwrap_or([1.0; 3]),
+            diffuse_texture: m.diffuse_texture.map(|t| asset_path(&t)),
+        })
+        .collect();
+
+    Ok(Obj { parts, materials })
+}
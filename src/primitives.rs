@@ -0,0 +1,292 @@
+use std::f32::consts::{PI, TAU};
+
+use rapier3d::prelude::{Isometry, SharedShape};
+
+use crate::math::{to_point3, Vec3};
+use crate::vertex::{PosTexCoordNormalVertex, Vertices};
+
+// Thickness given to the collider of planes, which Rapier only has as infinite half-spaces
+const PLANE_COLLIDER_HALF_THICKNESS: f32 = 0.01;
+
+// Shapes generated in code, centered on the origin with Y as their axis. Segments go around the
+// axis, rings along it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Sphere {
+        radius: f32,
+        segments: u32,
+        rings: u32,
+    },
+    // Cylinder of `half_height` with hemispheres on both ends
+    Capsule {
+        radius: f32,
+        half_height: f32,
+        segments: u32,
+        rings: u32,
+    },
+    Cylinder {
+        radius: f32,
+        half_height: f32,
+        segments: u32,
+    },
+    // Base at `-half_height`, apex at `half_height`
+    Cone {
+        radius: f32,
+        half_height: f32,
+        segments: u32,
+    },
+    // Horizontal, facing +Y
+    Plane {
+        half_width: f32,
+        half_depth: f32,
+        subdivisions: u32,
+    },
+    // Lying in the XZ plane
+    Torus {
+        radius: f32,
+        tube_radius: f32,
+        segments: u32,
+        tube_segments: u32,
+    },
+}
+
+impl Primitive {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Primitive::Sphere { .. } => "Sphere",
+            Primitive::Capsule { .. } => "Capsule",
+            Primitive::Cylinder { .. } => "Cylinder",
+            Primitive::Cone { .. } => "Cone",
+            Primitive::Plane { .. } => "Plane",
+            Primitive::Torus { .. } => "Torus",
+        }
+    }
+
+    pub fn geometry(&self) -> (Vertices, Vec<u32>) {
+        let mut geometry = Geometry::default();
+        match *self {
+            Primitive::Sphere {
+                radius,
+                segments,
+                rings,
+            } => geometry.add_lathe(segments, rings.max(2), |t| {
+                let angle = PI * (t - 0.5);
+                // Clamped so that the poles collapse to a single point
+                let normal = (angle.cos().max(0.0), angle.sin());
+                ((normal.0 * radius, normal.1 * radius), normal)
+            }),
+            Primitive::Capsule {
+                radius,
+                half_height,
+                segments,
+                rings,
+            } => {
+                // Each hemisphere gets half of the rings, the cylinder joins them
+                let half_rings = rings.max(2).div_ceil(2);
+                geometry.add_lathe(segments, half_rings * 2 + 1, |t| {
+                    let ring = t * (half_rings * 2 + 1) as f32;
+                    let (angle, y) = if ring <= half_rings as f32 {
+                        (PI * 0.5 * (ring / half_rings as f32 - 1.0), -half_height)
+                    } else {
+                        let ring = ring - half_rings as f32 - 1.0;
+                        (PI * 0.5 * ring / half_rings as f32, half_height)
+                    };
+                    let normal = (angle.cos().max(0.0), angle.sin());
+                    ((normal.0 * radius, y + normal.1 * radius), normal)
+                });
+            }
+            Primitive::Cylinder {
+                radius,
+                half_height,
+                segments,
+            } => {
+                geometry.add_lathe(segments, 1, |t| {
+                    ((radius, half_height * (2.0 * t - 1.0)), (1.0, 0.0))
+                });
+                geometry.add_cap(segments, radius, -half_height, false);
+                geometry.add_cap(segments, radius, half_height, true);
+            }
+            Primitive::Cone {
+                radius,
+                half_height,
+                segments,
+            } => {
+                // The slant normal is the same along the whole side
+                let slant = (2.0 * half_height, radius);
+                let len = (slant.0 * slant.0 + slant.1 * slant.1).sqrt();
+                let normal = (slant.0 / len, slant.1 / len);
+                geometry.add_lathe(segments, 1, |t| {
+                    ((radius * (1.0 - t), half_height * (2.0 * t - 1.0)), normal)
+                });
+                geometry.add_cap(segments, radius, -half_height, false);
+            }
+            Primitive::Plane {
+                half_width,
+                half_depth,
+                subdivisions,
+            } => geometry.add_grid(subdivisions + 1, subdivisions + 1, |u, v| {
+                (
+                    Vec3::new(
+                        (2.0 * u - 1.0) * half_width,
+                        0.0,
+                        (2.0 * v - 1.0) * half_depth,
+                    ),
+                    Vec3::y(),
+                )
+            }),
+            Primitive::Torus {
+                radius,
+                tube_radius,
+                segments,
+                tube_segments,
+            } => geometry.add_grid(segments.max(3), tube_segments.max(3), |u, v| {
+                let (around, tube) = (TAU * u, TAU * v);
+                let center = Vec3::new(around.cos(), 0.0, around.sin()) * radius;
+                let normal = Vec3::new(
+                    tube.cos() * around.cos(),
+                    tube.sin(),
+                    tube.cos() * around.sin(),
+                );
+                (center + normal * tube_radius, normal)
+            }),
+        }
+        (geometry.vertices.into_iter().collect(), geometry.indices)
+    }
+
+    // Collider matching the geometry
+    pub fn shape(&self) -> SharedShape {
+        match *self {
+            Primitive::Sphere { radius, .. } => SharedShape::ball(radius),
+            Primitive::Capsule {
+                radius,
+                half_height,
+                ..
+            } => SharedShape::capsule_y(half_height, radius),
+            Primitive::Cylinder {
+                radius,
+                half_height,
+                ..
+            } => SharedShape::cylinder(half_height, radius),
+            Primitive::Cone {
+                radius,
+                half_height,
+                ..
+            } => SharedShape::cone(half_height, radius),
+            Primitive::Plane {
+                half_width,
+                half_depth,
+                ..
+            } => SharedShape::cuboid(half_width, PLANE_COLLIDER_HALF_THICKNESS, half_depth),
+            // Rapier has no torus, so it's approximated by capsules around the ring
+            Primitive::Torus {
+                radius,
+                tube_radius,
+                segments,
+                ..
+            } => {
+                let segments = segments.max(3);
+                let point = |i: u32| {
+                    let angle = TAU * i as f32 / segments as f32;
+                    to_point3(Vec3::new(angle.cos(), 0.0, angle.sin()) * radius)
+                };
+                SharedShape::compound(
+                    (0..segments)
+                        .map(|i| {
+                            let capsule = SharedShape::capsule(point(i), point(i + 1), tube_radius);
+                            (Isometry::identity(), capsule)
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Geometry {
+    vertices: Vec<PosTexCoordNormalVertex>,
+    indices: Vec<u32>,
+}
+
+impl Geometry {
+    // Grid of `columns` by `rows` quads, with an extra row and column of vertices so that
+    // texture coordinates can wrap around. `point` maps texture coordinates to a position and
+    // a normal.
+    fn add_grid(&mut self, columns: u32, rows: u32, point: impl Fn(f32, f32) -> (Vec3, Vec3)) {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (u, v) = (column as f32 / columns as f32, row as f32 / rows as f32);
+                let (position, normal) = point(u, v);
+                self.vertices.push(PosTexCoordNormalVertex {
+                    position: position.into(),
+                    tex_coords: [u, v],
+                    normal: normal.normalize().into(),
+                });
+            }
+        }
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let i00 = first + row * (columns + 1) + column;
+                let i01 = i00 + 1;
+                let i10 = i00 + columns + 1;
+                let i11 = i10 + 1;
+                self.indices
+                    .extend_from_slice(&[i00, i10, i01, i01, i10, i11]);
+            }
+        }
+    }
+
+    // Surface of revolution around Y. `profile` maps 0..1 from the bottom to the top to
+    // a (distance from the axis, height) point and the (radial, vertical) normal there.
+    fn add_lathe(
+        &mut self,
+        segments: u32,
+        rings: u32,
+        profile: impl Fn(f32) -> ((f32, f32), (f32, f32)),
+    ) {
+        self.add_grid(segments.max(3), rings, |u, v| {
+            // Going from +X towards +Z keeps the faces pointing outwards
+            let angle = TAU * u;
+            let (around_x, around_z) = (angle.cos(), angle.sin());
+            let ((r, y), (normal_r, normal_y)) = profile(v);
+            (
+                Vec3::new(r * around_x, y, r * around_z),
+                Vec3::new(normal_r * around_x, normal_y, normal_r * around_z),
+            )
+        });
+    }
+
+    // Disk closing a lathe at the height, facing up or down
+    fn add_cap(&mut self, segments: u32, radius: f32, y: f32, up: bool) {
+        let segments = segments.max(3);
+        let normal = if up {
+            [0.0, 1.0, 0.0]
+        } else {
+            [0.0, -1.0, 0.0]
+        };
+        let center = self.vertices.len() as u32;
+        self.vertices.push(PosTexCoordNormalVertex {
+            position: [0.0, y, 0.0],
+            tex_coords: [0.5, 0.5],
+            normal,
+        });
+        for i in 0..segments {
+            let angle = TAU * i as f32 / segments as f32;
+            let (x, z) = (angle.cos(), angle.sin());
+            self.vertices.push(PosTexCoordNormalVertex {
+                position: [x * radius, y, z * radius],
+                tex_coords: [0.5 + x * 0.5, 0.5 + z * 0.5],
+                normal,
+            });
+        }
+
+        for i in 0..segments {
+            let (a, b) = (center + 1 + i, center + 1 + (i + 1) % segments);
+            // Angles grow from +X towards +Z, which is clockwise seen from above
+            let triangle = if up { [center, b, a] } else { [center, a, b] };
+            self.indices.extend_from_slice(&triangle);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+const MAX_PASSES: u32 = 16;
+const HISTORY_LEN: usize = 60;
+const READBACK_BUFFER_COUNT: usize = 3;
+
+pub struct PassScope {
+    index: u32,
+    cpu_start: Instant,
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    passes: Vec<&'static str>,
+    mapped: Arc<AtomicBool>,
+    in_flight: bool,
+}
+
+#[derive(Default)]
+struct State {
+    frame_passes: Vec<&'static str>,
+    readbacks: Vec<Readback>,
+    // Durations in milliseconds, most recent last
+    history: Vec<(&'static str, VecDeque<f32>)>,
+}
+
+// Measures render passes using GPU timestamp queries when the device supports them, or CPU time
+// spent encoding and submitting passes otherwise. GPU results are read back a few frames later.
+pub struct Profiler {
+    query_set: Option<wgpu::QuerySet>,
+    resolve_buf: Option<wgpu::Buffer>,
+    timestamp_period: f32,
+    state: RefCell<State>,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return Self {
+                query_set: None,
+                resolve_buf: None,
+                timestamp_period: 0.0,
+                state: RefCell::new(State::default()),
+            };
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: None,
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_PASSES * 2,
+        });
+
+        let buf_size = (MAX_PASSES * 2) as u64 * wgpu::QUERY_SIZE as u64;
+        let resolve_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: buf_size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readbacks = (0..READBACK_BUFFER_COUNT)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size: buf_size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                passes: Vec::new(),
+                mapped: Arc::new(AtomicBool::new(false)),
+                in_flight: false,
+            })
+            .collect();
+
+        Self {
+            query_set: Some(query_set),
+            resolve_buf: Some(resolve_buf),
+            timestamp_period: queue.get_timestamp_period(),
+            state: RefCell::new(State {
+                readbacks,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn gpu_timestamps_supported(&self) -> bool {
+        self.query_set.is_some()
+    }
+
+    pub fn begin_pass(&self, name: &'static str) -> PassScope {
+        let mut state = self.state.borrow_mut();
+        state.frame_passes.push(name);
+        PassScope {
+            index: state.frame_passes.len() as u32 - 1,
+            cpu_start: Instant::now(),
+        }
+    }
+
+    pub fn timestamp_writes(
+        &self,
+        scope: &PassScope,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.query_set
+            .as_ref()
+            .filter(|_| scope.index < MAX_PASSES)
+            .map(|query_set| wgpu::RenderPassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(scope.index * 2),
+                end_of_pass_write_index: Some(scope.index * 2 + 1),
+            })
+    }
+
+    pub fn end_pass(&self, scope: PassScope) {
+        if self.query_set.is_none() {
+            let mut state = self.state.borrow_mut();
+            let name = state.frame_passes[scope.index as usize];
+            let ms = scope.cpu_start.elapsed().as_secs_f32() * 1000.0;
+            state.record(name, ms);
+        }
+    }
+
+    // Should be called once per frame after all passes have been submitted.
+    pub fn end_frame(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let mut state = self.state.borrow_mut();
+        let passes = std::mem::take(&mut state.frame_passes);
+
+        let (Some(query_set), Some(resolve_buf)) = (&self.query_set, &self.resolve_buf) else {
+            return;
+        };
+
+        let pass_count = (passes.len() as u32).min(MAX_PASSES);
+        // If all buffers are still in flight this frame's results are simply dropped
+        if let Some(readback) = state.readbacks.iter_mut().find(|r| !r.in_flight) {
+            if pass_count > 0 {
+                let byte_count = (pass_count * 2) as u64 * wgpu::QUERY_SIZE as u64;
+                let mut encoder =
+                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                encoder.resolve_query_set(query_set, 0..pass_count * 2, resolve_buf, 0);
+                encoder.copy_buffer_to_buffer(resolve_buf, 0, &readback.buffer, 0, byte_count);
+                queue.submit(Some(encoder.finish()));
+
+                let mapped = Arc::clone(&readback.mapped);
+                readback
+                    .buffer
+                    .slice(..byte_count)
+                    .map_async(wgpu::MapMode::Read, move |r| {
+                        if r.is_ok() {
+                            mapped.store(true, Ordering::Release);
+                        }
+                    });
+                readback.passes = passes;
+                readback.passes.truncate(pass_count as usize);
+                readback.in_flight = true;
+            }
+        }
+
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut results = Vec::new();
+        for readback in state.readbacks.iter_mut().filter(|r| r.in_flight) {
+            if !readback.mapped.swap(false, Ordering::Acquire) {
+                continue;
+            }
+
+            let byte_count = readback.passes.len() as u64 * 2 * wgpu::QUERY_SIZE as u64;
+            {
+                let data = readback.buffer.slice(..byte_count).get_mapped_range();
+                let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                for (i, &name) in readback.passes.iter().enumerate() {
+                    let ticks = timestamps[i * 2 + 1].saturating_sub(timestamps[i * 2]);
+                    let ms = ticks as f32 * self.timestamp_period / 1_000_000.0;
+                    results.push((name, ms));
+                }
+            }
+            readback.buffer.unmap();
+            readback.in_flight = false;
+        }
+
+        for (name, ms) in results {
+            state.record(name, ms);
+        }
+    }
+
+    // Average pass durations in milliseconds over the last few frames
+    pub fn timings(&self) -> Vec<(&'static str, f32)> {
+        self.state
+            .borrow()
+            .history
+            .iter()
+            .map(|(name, h)| (*name, h.iter().sum::<f32>() / h.len().max(1) as f32))
+            .collect()
+    }
+}
+
+impl State {
+    fn record(&mut self, name: &'static str, ms: f32) {
+        let history = match self.history.iter_mut().position(|(n, _)| *n == name) {
+            Some(i) => &mut self.history[i].1,
+            None => {
+                self.history
+                    .push((name, VecDeque::with_capacity(HISTORY_LEN)));
+                &mut self.history.last_mut().unwrap().1
+            }
+        };
+
+        if history.len() >= HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(ms);
+    }
+}
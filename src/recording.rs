@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::capture::{timestamped_path, TextureReadback};
+use crate::graphics::Graphics;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub enum RecordingFormat {
+    // Numbered PNG files in a directory
+    #[default]
+    PngSequence,
+    // Uncompressed YUV 4:4:4 stream in a single file
+    Y4m,
+}
+
+impl FromStr for RecordingFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "png" => Ok(Self::PngSequence),
+            "y4m" => Ok(Self::Y4m),
+            _ => bail!("Unknown recording format '{s}', expected 'png' or 'y4m'"),
+        }
+    }
+}
+
+enum Output {
+    PngSequence {
+        dir: PathBuf,
+        next_index: u32,
+    },
+    Y4m {
+        file: BufWriter<File>,
+        size: Option<(u32, u32)>,
+    },
+}
+
+// Records presented frames. Frames are read back synchronously and encoded on a separate thread,
+// the simulation is expected to advance by a fixed `dt()` per recorded frame.
+pub struct Recorder {
+    frames: Option<mpsc::SyncSender<image::RgbaImage>>,
+    writer: Option<JoinHandle<Result<()>>>,
+    path: PathBuf,
+}
+
+impl Recorder {
+    const FPS: u32 = 60;
+    // Max frames waiting for encoding before the recording starts blocking the main loop
+    const QUEUE_LEN: usize = 8;
+
+    pub fn start(format: RecordingFormat) -> Result<Self> {
+        let (path, mut output) = match format {
+            RecordingFormat::PngSequence => {
+                let dir = timestamped_path("recording", "frames");
+                std::fs::create_dir_all(&dir)?;
+                (dir.clone(), Output::PngSequence { dir, next_index: 0 })
+            }
+            RecordingFormat::Y4m => {
+                let path = timestamped_path("recording", "y4m");
+                let file = BufWriter::new(File::create(&path)?);
+                (path, Output::Y4m { file, size: None })
+            }
+        };
+
+        let (tx, rx) = mpsc::sync_channel(Self::QUEUE_LEN);
+        let writer = std::thread::spawn(move || {
+            for frame in rx {
+                output.write(&frame)?;
+            }
+            output.finish()
+        });
+
+        println!("Recording to {}", path.display());
+
+        Ok(Self {
+            frames: Some(tx),
+            writer: Some(writer),
+            path,
+        })
+    }
+
+    pub fn dt(&self) -> f32 {
+        1.0 / Self::FPS as f32
+    }
+
+    pub fn record(&mut self, gfx: &Graphics, texture: &wgpu::Texture) -> Result<()> {
+        let frame = TextureReadback::new(gfx, texture)?.wait(gfx)?;
+        self.frames
+            .as_ref()
+            .unwrap()
+            .send(frame)
+            // The writer thread has quit, the actual error comes from joining it
+            .or_else(|_| self.join_writer())
+    }
+
+    pub fn stop(mut self) -> Result<()> {
+        self.join_writer()?;
+        println!("Recording saved to {}", self.path.display());
+        Ok(())
+    }
+
+    fn join_writer(&mut self) -> Result<()> {
+        self.frames = None;
+        match self.writer.take() {
+            Some(writer) => writer
+                .join()
+                .map_err(|_| anyhow!("Recording thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Output {
+    fn write(&mut self, frame: &image::RgbaImage) -> Result<()> {
+        match self {
+            Output::PngSequence { dir, next_index } => {
+                frame.save(dir.join(format!("frame-{next_index:06}.png")))?;
+                *next_index += 1;
+            }
+
+            Output::Y4m { file, size } => {
+                let (w, h) = frame.dimensions();
+                match size {
+                    None => {
+                        writeln!(
+                            file,
+                            "YUV4MPEG2 W{w} H{h} F{}:1 Ip A1:1 C444",
+                            Recorder::FPS
+                        )?;
+                        *size = Some((w, h));
+                    }
+                    Some(size) if *size != (w, h) => {
+                        bail!("Y4M recording does not support changing frame size")
+                    }
+                    _ => (),
+                }
+
+                // BT.601 limited range, planar Y, Cb, Cr
+                let mut planes = vec![0u8; (w * h * 3) as usize];
+                let (y_plane, rest) = planes.split_at_mut((w * h) as usize);
+                let (u_plane, v_plane) = rest.split_at_mut((w * h) as usize);
+                for (i, px) in frame.pixels().enumerate() {
+                    let [r, g, b, _] = px.0.map(|c| c as f32);
+                    y_plane[i] = (16.0 + (65.738 * r + 129.057 * g + 25.064 * b) / 256.0) as u8;
+                    u_plane[i] = (128.0 + (-37.945 * r - 74.494 * g + 112.439 * b) / 256.0) as u8;
+                    v_plane[i] = (128.0 + (112.439 * r - 94.154 * g - 18.285 * b) / 256.0) as u8;
+                }
+
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&planes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        if let Output::Y4m { mut file, .. } = self {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
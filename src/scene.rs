@@ -1,23 +1,91 @@
+use std::collections::HashSet;
+
 use hecs::{Entity, World};
+use rapier3d::prelude::RigidBodyType;
 use winit::window::Window;
 
-use crate::assets::Assets;
+use crate::animation::{Easing, Track};
+use crate::assets::{Assets, MaterialHandle, MeshHandle};
+use crate::capture::Screenshots;
 use crate::components::{
-    Camera, Grab, Material, Mesh, Player, PlayerTarget, RENDER_TAG_DEBUG_UI, RENDER_TAG_POST_PROCESS, RENDER_TAG_SCENE,
-    RenderOrder, RenderTags, RigidBody, RigidBodyParams, Transform,
+    AnimationPlayer, Camera, Grab, Material, Mesh, Name, Parent, PendingRigidBody, Player, PlayerTarget, RENDER_TAG_DEBUG_UI, RENDER_TAG_POST_PROCESS, RENDER_TAG_SCENE,
+    RenderOrder, RenderTags, RigidBody, RigidBodyParams, Skin, Transform, Tween, TweenMode,
 };
 use crate::graphics::{Graphics, SurfaceSize};
+use crate::ibl::Lighting;
 use crate::input::{Input, InputAction};
-use crate::materials;
-use crate::math::Vec3;
+use crate::inspector::Inspector;
+use crate::materials::{self, Fog, SkyParams};
+use crate::math::{UnitQuat, Vec2, Vec3};
+use crate::mesh;
+use crate::model::Model;
 use crate::physics::Physics;
+use crate::primitives::Primitive;
+use crate::terrain::Heightmap;
+use crate::time_of_day::TimeOfDay;
+use crate::ui::Ui;
+
+// Collider given to spawned shapes
+#[derive(Clone, Copy, PartialEq)]
+enum SpawnCollider {
+    // Rapier shape matching the box or primitive exactly
+    Shape,
+    ConvexHull,
+    ConvexDecomposition,
+    // Fixed in place
+    TriMesh,
+}
+
+impl SpawnCollider {
+    const ALL: [Self; 4] = [
+        Self::Shape,
+        Self::ConvexHull,
+        Self::ConvexDecomposition,
+        Self::TriMesh,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Shape => "Shape",
+            Self::ConvexHull => "Convex hull",
+            Self::ConvexDecomposition => "Convex decomposition",
+            Self::TriMesh => "Triangle mesh",
+        }
+    }
+}
+
+enum ScreenshotRequest {
+    // Final frame presented on the screen
+    Frame,
+    // Color texture of a camera's render target
+    Camera(Entity),
+}
 
 pub struct Scene {
     world: World,
     physics: Physics,
+    inspector: Inspector,
     postprocessor: Entity,
     player: Entity,
+    sky: Entity,
+    // The sky entity switches between these
+    skybox_material: MaterialHandle,
+    procedural_sky_material: MaterialHandle,
+    // Drives the procedural sky and lighting
+    time_of_day: TimeOfDay,
+    fog: Fog,
     spawned_box_at_startup: bool,
+    // Shapes that can be spawned instead of boxes, with their meshes
+    spawn_primitives: Vec<(Primitive, MeshHandle)>,
+    // Index into `spawn_primitives`, boxes are spawned if none
+    spawn_shape: Option<usize>,
+    spawn_collider: SpawnCollider,
+    // Set from the UI, handled on the next update
+    spawn_requested: bool,
+    screenshot_request: Option<ScreenshotRequest>,
+    screenshots: Screenshots,
+    // Mesh and material pairs that failed to render, skipped without reporting them again
+    render_errors: HashSet<(MeshHandle, MaterialHandle)>,
 }
 
 impl Scene {
@@ -25,9 +93,22 @@ impl Scene {
         let mut scene = Self {
             world: World::new(),
             physics: Physics::new(),
+            inspector: Inspector::default(),
             player: Entity::DANGLING,
             postprocessor: Entity::DANGLING,
+            sky: Entity::DANGLING,
+            skybox_material: MaterialHandle::default(),
+            procedural_sky_material: MaterialHandle::default(),
+            time_of_day: TimeOfDay::default(),
+            fog: Fog::default(),
             spawned_box_at_startup: false,
+            spawn_primitives: Vec::new(),
+            spawn_shape: None,
+            spawn_collider: SpawnCollider::Shape,
+            spawn_requested: false,
+            screenshot_request: None,
+            screenshots: Screenshots::default(),
+            render_errors: HashSet::new(),
         };
 
         // Player
@@ -44,28 +125,70 @@ impl Scene {
         // Floor
         scene.spawn_floor(gfx, assets);
 
+        // Terrain
+        scene.spawn_terrain(gfx, assets);
+
+        // Imported models
+        let table = pollster::block_on(Model::from_file(gfx, assets, "table.glb")).unwrap();
+        table.spawn(
+            gfx,
+            &mut scene.world,
+            assets,
+            Transform::from_pos(Vec3::new(4.0, 0.5, -3.0)),
+        );
+        let vase = pollster::block_on(Model::from_file(gfx, assets, "vase.obj")).unwrap();
+        vase.spawn(
+            gfx,
+            &mut scene.world,
+            assets,
+            Transform::from_pos(Vec3::new(-4.0, 0.5, -3.0)),
+        );
+        let tentacle = pollster::block_on(Model::from_file(gfx, assets, "tentacle.glb")).unwrap();
+        let tentacle = tentacle.spawn(
+            gfx,
+            &mut scene.world,
+            assets,
+            Transform::from_pos(Vec3::new(0.0, 0.5, -5.0)),
+        );
+        if let Ok(mut player) = scene.world.get::<&mut AnimationPlayer>(tentacle) {
+            player.play("Sway");
+        }
+
+        // Moving platform and door
+        scene.spawn_tweened_bodies(gfx, assets);
+
+        // Spawnable primitives
+        scene.spawn_primitives = spawn_primitives()
+            .into_iter()
+            .map(|p| {
+                let mesh = mesh::Mesh::from_parts(gfx, &[p.geometry()], true).unwrap();
+                let mesh = assets.add_mesh(mesh);
+                (p, mesh)
+            })
+            .collect();
+
         // Skybox
         // Spawning skybox somewhere in the middle to ensure the sorting by render order works and it still shows up
         // in the background.
-        let material = assets.add_skybox_material(gfx, assets.skybox_texture);
-        scene.world.spawn((
+        scene.skybox_material = assets.add_skybox_material(gfx, assets.skybox_texture);
+        scene.procedural_sky_material = assets.add_sky_material(gfx);
+        scene.sky = scene.world.spawn((
             Transform::default(),
             Mesh(assets.quad_mesh),
-            Material(material),
+            Material(scene.skybox_material),
             RenderOrder(-100),
             RenderTags(RENDER_TAG_SCENE),
         ));
 
         // Post-processor
-        let pp_src_tex = scene
+        let pp_source = scene
             .world
             .query_one_mut::<&Camera>(scene.player)
             .unwrap()
             .target()
             .as_ref()
-            .unwrap()
-            .color_tex();
-        let material = assets.add_postprocess_material(gfx, pp_src_tex);
+            .unwrap();
+        let material = assets.add_postprocess_material(gfx, pp_source);
         scene.postprocessor = scene.world.spawn((
             Transform::default(),
             Camera::new(1.0, RENDER_TAG_POST_PROCESS | RENDER_TAG_DEBUG_UI, None),
@@ -87,33 +210,175 @@ impl Scene {
         assets: &mut Assets,
         new_canvas_size: &Option<SurfaceSize>,
     ) {
+        Tween::update(dt, &mut self.world, &mut self.physics);
+        PendingRigidBody::update(&mut self.world, &mut self.physics);
         self.physics.update(dt);
 
         Player::update(dt, &mut self.world, &mut self.physics, input, window);
         Grab::update(&mut self.world, input, &mut self.physics);
         PlayerTarget::update(&mut self.world);
 
-        if input.action_activated(InputAction::Spawn) || !self.spawned_box_at_startup {
+        let spawn_requested = std::mem::take(&mut self.spawn_requested);
+        if spawn_requested
+            || input.action_activated(InputAction::Spawn)
+            || !self.spawned_box_at_startup
+        {
             let player_transform = self.world.query_one_mut::<&Transform>(self.player).unwrap();
-            let pos = if self.spawned_box_at_startup {
-                player_transform.position() + player_transform.forward().xyz() * 5.0
+            let (pos, shape, collider) = if self.spawned_box_at_startup {
+                let pos = player_transform.position() + player_transform.forward().xyz() * 5.0;
+                (pos, self.spawn_shape, self.spawn_collider)
             } else {
                 self.spawned_box_at_startup = true;
-                Vec3::y_axis().xyz() * 5.0
+                (Vec3::y_axis().xyz() * 5.0, None, SpawnCollider::Shape)
             };
-            self.spawn_box(pos, Vec3::from_element(1.0), gfx, assets);
+            if let Err(e) = self.spawn_object(pos, shape, collider, gfx, assets) {
+                eprintln!("Failed to spawn: {e:#}");
+            }
+        }
+
+        if input.action_activated(InputAction::Screenshot) {
+            self.screenshot_request = Some(ScreenshotRequest::Frame);
         }
 
         self.sync_physics();
+        AnimationPlayer::update(dt, &mut self.world);
+        Parent::update(&mut self.world);
+        Skin::update(&self.world, assets);
+        self.update_sky(dt, gfx, assets);
 
         if let Some(new_size) = new_canvas_size {
             self.handle_canvas_resize(new_size, gfx, assets);
         }
     }
 
-    pub fn render(&mut self, gfx: &Graphics, assets: &mut Assets) {
-        self.render_with_camera(self.player, gfx, assets);
-        self.render_with_camera(self.postprocessor, gfx, assets);
+    pub fn update_ui(&mut self, dt: f32, gfx: &Graphics, ui: &Ui, assets: &mut Assets) {
+        egui::Window::new("Debug").show(ui.ctx(), |ui| {
+            ui.label(format!("Frame time: {:.2} ms", dt * 1000.0));
+            let timing_kind = if gfx.profiler().gpu_timestamps_supported() {
+                "GPU"
+            } else {
+                "CPU"
+            };
+            for (pass, ms) in gfx.profiler().timings() {
+                ui.label(format!("{pass} pass ({timing_kind}): {ms:.3} ms"));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Spawn").clicked() {
+                    self.spawn_requested = true;
+                }
+                let name = |shape: Option<usize>| {
+                    shape.map_or("Box", |i| self.spawn_primitives[i].0.name())
+                };
+                egui::ComboBox::from_id_salt("spawn_shape")
+                    .selected_text(name(self.spawn_shape))
+                    .show_ui(ui, |ui| {
+                        let mut shape = self.spawn_shape;
+                        let primitives = (0..self.spawn_primitives.len()).map(Some);
+                        for option in std::iter::once(None).chain(primitives) {
+                            ui.selectable_value(&mut shape, option, name(option));
+                        }
+                        self.spawn_shape = shape;
+                    });
+                egui::ComboBox::from_id_salt("spawn_collider")
+                    .selected_text(self.spawn_collider.name())
+                    .show_ui(ui, |ui| {
+                        for collider in SpawnCollider::ALL {
+                            ui.selectable_value(
+                                &mut self.spawn_collider,
+                                collider,
+                                collider.name(),
+                            );
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Screenshot").clicked() {
+                    self.screenshot_request = Some(ScreenshotRequest::Frame);
+                }
+                if ui.button("Screenshot (scene only)").clicked() {
+                    self.screenshot_request = Some(ScreenshotRequest::Camera(self.player));
+                }
+            });
+            ui.collapsing("Sky", |ui| self.sky_ui(ui, gfx, assets));
+            ui.collapsing("Fog", |ui| self.fog_ui(ui));
+        });
+
+        self.inspector
+            .show(ui.ctx(), &mut self.world, &mut self.physics, assets);
+    }
+
+    pub fn render(&mut self, gfx: &Graphics, assets: &mut Assets, ui: &mut Ui, window: &Window) {
+        self.render_with_camera(self.player, "Scene", gfx, assets);
+        self.update_fog(gfx, assets);
+        self.render_with_camera(self.postprocessor, "Post-process", gfx, assets);
+        ui.render(gfx, window);
+
+        match self.screenshot_request.take() {
+            Some(ScreenshotRequest::Frame) => self.screenshots.capture(gfx, gfx.surface_tex()),
+            Some(ScreenshotRequest::Camera(camera)) => {
+                let cam = self.world.get::<&Camera>(camera).unwrap();
+                if let Some(target) = cam.target() {
+                    self.screenshots.capture(gfx, target.color_tex().texture());
+                }
+            }
+            None => (),
+        }
+        self.screenshots.update(gfx);
+    }
+
+    fn sky_ui(&mut self, ui: &mut egui::Ui, gfx: &Graphics, assets: &mut Assets) {
+        let mut material = self.world.get::<&mut Material>(self.sky).unwrap();
+        let mut procedural = material.0 == self.procedural_sky_material;
+        if ui.checkbox(&mut procedural, "Procedural").changed() {
+            material.0 = if procedural {
+                self.procedural_sky_material
+            } else {
+                self.skybox_material
+            };
+        }
+
+        // The cubemap is a still picture, so time only passes with the procedural sky
+        if !procedural {
+            return;
+        }
+        self.time_of_day.show_ui(ui);
+
+        let materials::Material::Sky(sky) = assets.material_mut(self.procedural_sky_material)
+        else {
+            return;
+        };
+        let mut params = sky.params();
+        ui.add(egui::Slider::new(&mut params.turbidity, 1.0..=10.0).text("Turbidity"));
+        ui.horizontal(|ui| {
+            let mut color = params.ground_color.into();
+            ui.color_edit_button_rgb(&mut color);
+            params.ground_color = color.into();
+            ui.label("Ground color");
+        });
+        if ui.button("Reset").clicked() {
+            params = self.time_of_day.sky_params(SkyParams::default());
+        }
+
+        sky.set_params(gfx, params);
+    }
+
+    fn update_sky(&mut self, dt: f32, gfx: &Graphics, assets: &mut Assets) {
+        let material = self.world.get::<&Material>(self.sky).unwrap().0;
+        if material != self.procedural_sky_material {
+            assets.environment.set_lighting(gfx, Lighting::default());
+            assets.update_environment(gfx, None);
+            return;
+        }
+
+        self.time_of_day.update(dt);
+        if let materials::Material::Sky(sky) = assets.material_mut(material) {
+            let params = self.time_of_day.sky_params(sky.params());
+            sky.set_params(gfx, params);
+            assets.update_environment(gfx, Some(&params));
+        }
+        assets
+            .environment
+            .set_lighting(gfx, self.time_of_day.lighting());
     }
 
     fn handle_canvas_resize(
@@ -129,10 +394,42 @@ impl Scene {
             .unwrap()
             .resize((new_size.width, new_size.height), gfx);
 
-        let color_tex = player_cam.target().as_ref().unwrap().color_tex();
+        let source = player_cam.target().as_ref().unwrap();
         let mut material = self.world.get::<&mut Material>(self.postprocessor).unwrap();
         assets.remove_material(material.0);
-        material.0 = assets.add_postprocess_material(gfx, color_tex);
+        material.0 = assets.add_postprocess_material(gfx, source);
+    }
+
+    fn fog_ui(&mut self, ui: &mut egui::Ui) {
+        let fog = &mut self.fog;
+        ui.add(
+            egui::Slider::new(&mut fog.density, 0.0..=0.5)
+                .logarithmic(true)
+                .text("Density"),
+        );
+        ui.add(egui::Slider::new(&mut fog.height_falloff, 0.0..=1.0).text("Height falloff"));
+        ui.add(egui::Slider::new(&mut fog.height, -10.0..=50.0).text("Height"));
+        if ui.button("Reset").clicked() {
+            *fog = Fog::default();
+        }
+    }
+
+    fn update_fog(&self, gfx: &Graphics, assets: &mut Assets) {
+        let sky_material = self.world.get::<&Material>(self.sky).unwrap().0;
+        let sky = match assets.material(sky_material) {
+            materials::Material::Sky(m) => Some(m.params()),
+            _ => None,
+        };
+
+        let mut player = self
+            .world
+            .query_one::<(&Camera, &Transform)>(self.player)
+            .unwrap();
+        let (cam, cam_tr) = player.get().unwrap();
+        let post_material = self.world.get::<&Material>(self.postprocessor).unwrap().0;
+        if let materials::Material::PostProcess(m) = assets.material_mut(post_material) {
+            m.set_fog(gfx, cam, cam_tr, &self.fog, sky.as_ref());
+        }
     }
 
     fn spawn_floor(&mut self, gfx: &Graphics, assets: &mut Assets) {
@@ -146,7 +443,10 @@ impl Scene {
             },
             &mut self.physics,
         );
-        let material = assets.add_textured_material(gfx, assets.bricks_texture);
+        let material = assets.add_textured_material(gfx, assets.bricks_texture, false);
+        if let materials::Material::Textured(m) = assets.material_mut(material) {
+            m.set_uv_tiling(gfx, Vec2::new(scale.x, scale.z));
+        }
         self.world.spawn((
             Transform::new(pos, scale),
             Mesh(assets.box_mesh),
@@ -157,27 +457,163 @@ impl Scene {
         ));
     }
 
-    fn spawn_box(&mut self, pos: Vec3, scale: Vec3, gfx: &Graphics, assets: &mut Assets) {
-        let body = RigidBody::cuboid(
+    // Kinematic bodies animated by tweens, pushing dynamic bodies around
+    fn spawn_tweened_bodies(&mut self, gfx: &Graphics, assets: &mut Assets) {
+        let lift_bottom = Vec3::new(6.0, 0.7, 6.0);
+        let lift_top = lift_bottom + Vec3::y() * 4.0;
+        let lift = Tween {
+            translation: Track::default()
+                .key(0.0, lift_bottom, Easing::Linear)
+                .key(1.0, lift_bottom, Easing::Step)
+                .key(4.0, lift_top, Easing::EaseInOut)
+                .key(5.0, lift_top, Easing::Step),
+            mode: TweenMode::PingPong,
+            ..Tween::default()
+        };
+        let lift_scale = Vec3::new(1.5, 0.2, 1.5);
+        self.spawn_kinematic_box("Lift", lift_bottom, lift_scale, lift, gfx, assets);
+
+        // Quarter turns slowing down at the end
+        let door_pos = Vec3::new(-6.0, 2.0, 6.0);
+        let mut rotation = Track::default();
+        for i in 0..=4 {
+            let angle = i as f32 * std::f32::consts::FRAC_PI_2;
+            let rot = UnitQuat::from_axis_angle(&Vec3::y_axis(), angle);
+            rotation = rotation.key(i as f32 * 2.0, rot, Easing::EaseOut);
+        }
+        let door = Tween {
+            rotation,
+            ..Tween::default()
+        };
+        let door_scale = Vec3::new(2.0, 1.5, 0.1);
+        self.spawn_kinematic_box("Revolving door", door_pos, door_scale, door, gfx, assets);
+
+        // Speeds up to shove, then slowly comes back
+        let pusher_start = Vec3::new(-9.0, 1.0, 1.0);
+        let pusher = Tween {
+            translation: Track::default()
+                .key(0.0, pusher_start, Easing::Linear)
+                .key(1.5, pusher_start + Vec3::x() * 6.0, Easing::EaseIn)
+                .key(4.5, pusher_start, Easing::Linear),
+            ..Tween::default()
+        };
+        let pusher_scale = Vec3::new(0.5, 0.5, 1.5);
+        self.spawn_kinematic_box("Pusher", pusher_start, pusher_scale, pusher, gfx, assets);
+    }
+
+    fn spawn_kinematic_box(
+        &mut self,
+        name: &str,
+        pos: Vec3,
+        scale: Vec3,
+        tween: Tween,
+        gfx: &Graphics,
+        assets: &mut Assets,
+    ) {
+        let mut body = RigidBody::cuboid(
             RigidBodyParams {
                 pos,
                 scale,
-                movable: true,
+                movable: false,
             },
             &mut self.physics,
         );
-        let material = assets.add_textured_material(gfx, assets.crate_texture);
+        body.set_body_type(&mut self.physics, RigidBodyType::KinematicPositionBased);
+        let material = assets.add_textured_material(gfx, assets.bricks_texture, true);
         self.world.spawn((
             Transform::new(pos, scale),
             Mesh(assets.box_mesh),
             Material(material),
             body,
+            tween,
+            Name(name.to_string()),
             RenderOrder(0),
             RenderTags(RENDER_TAG_SCENE),
         ));
     }
 
-    fn render_with_camera(&mut self, camera: Entity, gfx: &Graphics, assets: &mut Assets) {
+    fn spawn_terrain(&mut self, gfx: &Graphics, assets: &mut Assets) {
+        // The heightmap is flat in the middle, where the floor is
+        let pos = Vec3::new(0.0, -0.5, 0.0);
+        let size = Vec3::new(100.0, 8.0, 100.0);
+        let heightmap = pollster::block_on(Heightmap::from_file("heightmap.png")).unwrap();
+
+        let mesh = mesh::Mesh::from_parts(gfx, &heightmap.chunks(size, 32), false).unwrap();
+        let mesh = assets.add_mesh(mesh);
+        let body = RigidBody::heightfield(&heightmap, pos, size, &mut self.physics);
+        let material = assets.add_textured_material(gfx, assets.bricks_texture, true);
+        if let materials::Material::Textured(m) = assets.material_mut(material) {
+            m.set_uv_tiling(gfx, Vec2::new(size.x, size.z) / 4.0);
+        }
+        self.world.spawn((
+            Transform::from_pos(pos),
+            Mesh(mesh),
+            Material(material),
+            body,
+            RenderOrder(0),
+            RenderTags(RENDER_TAG_SCENE),
+        ));
+    }
+
+    // Spawns a box if no primitive is given
+    fn spawn_object(
+        &mut self,
+        pos: Vec3,
+        primitive: Option<usize>,
+        collider: SpawnCollider,
+        gfx: &Graphics,
+        assets: &mut Assets,
+    ) -> anyhow::Result<()> {
+        let scale = Vec3::from_element(1.0);
+        let params = RigidBodyParams {
+            pos,
+            scale,
+            movable: true,
+        };
+        let mesh = primitive.map_or(assets.box_mesh, |i| self.spawn_primitives[i].1);
+        let mut entity = hecs::EntityBuilder::new();
+        let physics = &mut self.physics;
+        match collider {
+            SpawnCollider::Shape => match primitive {
+                Some(i) => entity.add(RigidBody::primitive(
+                    &self.spawn_primitives[i].0,
+                    pos,
+                    true,
+                    physics,
+                )),
+                None => entity.add(RigidBody::cuboid(params, physics)),
+            },
+            SpawnCollider::ConvexHull => {
+                entity.add(RigidBody::convex_hull(assets.mesh(mesh), params, physics)?)
+            }
+            // Stays in place until its collider is ready
+            SpawnCollider::ConvexDecomposition => {
+                entity.add(RigidBody::convex_decomposition(assets.mesh(mesh), params)?)
+            }
+            SpawnCollider::TriMesh => {
+                entity.add(RigidBody::trimesh(assets.mesh(mesh), pos, scale, physics)?)
+            }
+        };
+
+        let material = assets.add_textured_material(gfx, assets.crate_texture, true);
+        entity.add_bundle((
+            Transform::new(pos, scale),
+            Mesh(mesh),
+            Material(material),
+            RenderOrder(0),
+            RenderTags(RENDER_TAG_SCENE),
+        ));
+        self.world.spawn(entity.build());
+        Ok(())
+    }
+
+    fn render_with_camera(
+        &mut self,
+        camera: Entity,
+        pass_name: &'static str,
+        gfx: &Graphics,
+        assets: &mut Assets,
+    ) {
         if let Some((cam, cam_tr)) = self
             .world
             .query_one::<(&Camera, &Transform)>(camera)
@@ -202,19 +638,26 @@ impl Scene {
 
             let bundles = meshes
                 .into_iter()
-                .map(|(mesh, material, transform, _)| {
+                .filter_map(|(mesh, material, transform, _)| {
                     match assets.material_mut(material.0) {
                         materials::Material::Color(m) => m.set_wvp(gfx, cam, cam_tr, transform),
                         materials::Material::Skybox(m) => m.set_wvp(gfx, cam, cam_tr),
+                        materials::Material::Sky(m) => m.set_wvp(gfx, cam, cam_tr),
                         materials::Material::Textured(m) => m.set_wvp(gfx, cam, cam_tr, transform),
                         materials::Material::PostProcess(_) => (),
                     }
                     gfx.build_render_bundle(mesh.0, material.0, cam.target().as_ref(), assets)
+                        .map_err(|e| {
+                            if self.render_errors.insert((mesh.0, material.0)) {
+                                eprintln!("Failed to render: {e:#}");
+                            }
+                        })
+                        .ok()
                 })
                 // TODO Avoid vec allocation
                 .collect::<Vec<wgpu::RenderBundle>>();
 
-            gfx.render_pass(&bundles, cam.target().as_ref());
+            gfx.render_pass(pass_name, &bundles, cam.target().as_ref());
         }
     }
 
@@ -225,3 +668,42 @@ impl Scene {
         }
     }
 }
+
+// Roughly the size of the spawned boxes, so that they stack together
+fn spawn_primitives() -> Vec<Primitive> {
+    vec![
+        Primitive::Sphere {
+            radius: 1.0,
+            segments: 32,
+            rings: 16,
+        },
+        Primitive::Capsule {
+            radius: 0.6,
+            half_height: 0.6,
+            segments: 32,
+            rings: 16,
+        },
+        // Barrel
+        Primitive::Cylinder {
+            radius: 0.8,
+            half_height: 1.0,
+            segments: 32,
+        },
+        Primitive::Cone {
+            radius: 1.0,
+            half_height: 1.0,
+            segments: 32,
+        },
+        Primitive::Plane {
+            half_width: 1.5,
+            half_depth: 1.5,
+            subdivisions: 0,
+        },
+        Primitive::Torus {
+            radius: 1.0,
+            tube_radius: 0.3,
+            segments: 32,
+            tube_segments: 16,
+        },
+    ]
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use anyhow::*;
+
+use crate::file;
+
+// WGSL source after resolving preprocessor directives
+pub struct PreprocessedShader {
+    pub source: String,
+    // The root file and all files included into it, relative to the assets directory
+    pub files: Vec<String>,
+}
+
+struct Condition {
+    active: bool,
+    parent_active: bool,
+    seen_else: bool,
+}
+
+struct State {
+    defines: HashMap<String, String>,
+    files: Vec<String>,
+    include_stack: Vec<String>,
+    output: String,
+}
+
+// Supported directives:
+// - `#include "path"` - inserts the file (path relative to the assets directory), each file is
+//   included at most once;
+// - `#define NAME [VALUE]` - defines a symbol, whole-word occurrences of it in the following code
+//   are replaced with the value if there is one;
+// - `#undef NAME`;
+// - `#ifdef NAME`, `#ifndef NAME`, `#else`, `#endif`.
+// `defines` are symbols defined before processing the file, used to select shader variants.
+pub async fn preprocess(file_path: &str, defines: &[&str]) -> Result<PreprocessedShader> {
+    let mut state = State {
+        defines: defines
+            .iter()
+            .map(|&d| (d.to_string(), String::new()))
+            .collect(),
+        files: Vec::new(),
+        include_stack: Vec::new(),
+        output: String::new(),
+    };
+
+    process_file(&mut state, file_path).await?;
+
+    Ok(PreprocessedShader {
+        source: state.output,
+        files: state.files,
+    })
+}
+
+async fn process_file(state: &mut State, path: &str) -> Result<()> {
+    if state.include_stack.iter().any(|p| p == path) {
+        bail!("Recursive include of {path}");
+    }
+    if state.files.iter().any(|p| p == path) {
+        return Ok(());
+    }
+
+    let src = file::read_string_asset(path)
+        .await
+        .with_context(|| format!("Failed to read {path}"))?;
+
+    state.files.push(path.to_string());
+    state.include_stack.push(path.to_string());
+
+    let mut conditions: Vec<Condition> = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let err_location = || format!("{path}:{}", i + 1);
+        let active = conditions.last().is_none_or(|c| c.active);
+
+        let Some(directive) = line.trim_start().strip_prefix('#') else {
+            if active {
+                substitute_defines(line, &state.defines, &mut state.output);
+                state.output.push('\n');
+            }
+            continue;
+        };
+
+        let (name, args) = directive
+            .trim()
+            .split_once(char::is_whitespace)
+            .map_or((directive.trim(), ""), |(n, a)| (n, a.trim()));
+
+        match name {
+            "ifdef" | "ifndef" => {
+                let defined = state.defines.contains_key(symbol(args, err_location)?);
+                conditions.push(Condition {
+                    active: active && defined == (name == "ifdef"),
+                    parent_active: active,
+                    seen_else: false,
+                });
+            }
+
+            "else" => {
+                let c = conditions
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("{}: #else without #ifdef", err_location()))?;
+                if c.seen_else {
+                    bail!("{}: duplicate #else", err_location());
+                }
+                c.seen_else = true;
+                c.active = c.parent_active && !c.active;
+            }
+
+            "endif" => {
+                conditions
+                    .pop()
+                    .ok_or_else(|| anyhow!("{}: #endif without #ifdef", err_location()))?;
+            }
+
+            _ if !active => (),
+
+            "define" => {
+                let (symbol, value) = args
+                    .split_once(char::is_whitespace)
+                    .map_or((args, ""), |(s, v)| (s, v.trim()));
+                let symbol = self::symbol(symbol, err_location)?;
+                state.defines.insert(symbol.to_string(), value.to_string());
+            }
+
+            "undef" => {
+                state.defines.remove(symbol(args, err_location)?);
+            }
+
+            "include" => {
+                let include_path = args
+                    .strip_prefix('"')
+                    .and_then(|a| a.strip_suffix('"'))
+                    .ok_or_else(|| anyhow!("{}: expected a quoted path", err_location()))?;
+                Box::pin(process_file(state, include_path)).await?;
+            }
+
+            _ => bail!("{}: unknown directive #{name}", err_location()),
+        }
+    }
+
+    if !conditions.is_empty() {
+        bail!("{path}: missing #endif");
+    }
+
+    state.include_stack.pop();
+
+    Ok(())
+}
+
+fn symbol(args: &str, err_location: impl Fn() -> String) -> Result<&str> {
+    let valid = args.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && args.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!("{}: invalid symbol '{args}'", err_location());
+    }
+    Ok(args)
+}
+
+fn substitute_defines(line: &str, defines: &HashMap<String, String>, output: &mut String) {
+    let is_ident_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        // Skip tokens that only look like identifiers, e.g. suffixes of number literals
+        let preceded_by_ident = rest[..start].ends_with(is_ident_char);
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        match defines.get(token) {
+            Some(value) if !value.is_empty() && !preceded_by_ident => output.push_str(value),
+            _ => output.push_str(token),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+}
@@ -0,0 +1,345 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU64;
+
+use anyhow::*;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::vertex::VertexAttribute;
+
+// Resource bindings declared by a shader module, keyed by (group, binding), and the inputs of
+// its vertex entry points keyed by location
+pub struct ShaderReflection {
+    bindings: BTreeMap<(u32, u32), ReflectedBinding>,
+    vertex_inputs: BTreeMap<u32, VertexInput>,
+}
+
+struct ReflectedBinding {
+    name: String,
+    visibility: wgpu::ShaderStages,
+    ty: wgpu::BindingType,
+    // Byte size of the uniform type
+    size: Option<u64>,
+}
+
+struct VertexInput {
+    name: String,
+    // WGSL type, e.g. vec3<f32>
+    ty: String,
+}
+
+impl ShaderReflection {
+    // Parses and validates WGSL, returning the module to create the shader from along with
+    // its reflection. `file_path` is only used in error messages.
+    pub fn from_wgsl(source: &str, file_path: &str) -> Result<(naga::Module, Self)> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| anyhow!("{}", e.emit_to_string_with_path(source, file_path)))?;
+        Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|e| anyhow!("{}", e.emit_to_string_with_path(source, file_path)))?;
+
+        // Visible to all stages rather than just the ones using the binding, so that using it
+        // from another stage doesn't change the layout when hot reloading
+        let visibility = module
+            .entry_points
+            .iter()
+            .fold(wgpu::ShaderStages::NONE, |v, ep| {
+                v | match ep.stage {
+                    naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                    naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                    naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                }
+            });
+
+        let mut bindings = BTreeMap::new();
+        for (_, var) in module.global_variables.iter() {
+            let Some(binding) = &var.binding else {
+                continue;
+            };
+            let name = var.name.clone().unwrap_or_default();
+            let location = format!(
+                "'{name}' at @group({}) @binding({})",
+                binding.group, binding.binding
+            );
+
+            let ty_inner = &module.types[var.ty].inner;
+            let mut size = None;
+            let ty = match (var.space, ty_inner) {
+                (naga::AddressSpace::Uniform, _) => {
+                    let s = ty_inner.size(module.to_ctx()) as u64;
+                    size = Some(s);
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(s),
+                    }
+                }
+                (naga::AddressSpace::Storage { access }, _) => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage {
+                        read_only: !access.contains(naga::StorageAccess::STORE),
+                    },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                (_, naga::TypeInner::Sampler { comparison }) => {
+                    wgpu::BindingType::Sampler(if *comparison {
+                        wgpu::SamplerBindingType::Comparison
+                    } else {
+                        wgpu::SamplerBindingType::Filtering
+                    })
+                }
+                (
+                    _,
+                    naga::TypeInner::Image {
+                        dim,
+                        arrayed,
+                        class,
+                    },
+                ) => {
+                    let view_dimension = match (dim, arrayed) {
+                        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                    };
+                    let (sample_type, multisampled) = match class {
+                        naga::ImageClass::Sampled { kind, multi } => (
+                            match kind {
+                                naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                                naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                                _ => wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            *multi,
+                        ),
+                        naga::ImageClass::Depth { multi } => {
+                            (wgpu::TextureSampleType::Depth, *multi)
+                        }
+                        naga::ImageClass::Storage { .. } => {
+                            bail!("{file_path}: {location}: storage textures are not supported")
+                        }
+                    };
+                    wgpu::BindingType::Texture {
+                        sample_type,
+                        view_dimension,
+                        multisampled,
+                    }
+                }
+                _ => bail!("{file_path}: {location}: unsupported binding type"),
+            };
+
+            bindings.insert(
+                (binding.group, binding.binding),
+                ReflectedBinding {
+                    name,
+                    visibility,
+                    ty,
+                    size,
+                },
+            );
+        }
+
+        let mut vertex_inputs = BTreeMap::new();
+        for ep in &module.entry_points {
+            if ep.stage != naga::ShaderStage::Vertex {
+                continue;
+            }
+            for arg in &ep.function.arguments {
+                // Inputs are either arguments or members of a struct argument
+                let members = match &module.types[arg.ty].inner {
+                    naga::TypeInner::Struct { members, .. } if arg.binding.is_none() => members
+                        .iter()
+                        .map(|m| (&m.name, m.ty, &m.binding))
+                        .collect(),
+                    _ => vec![(&arg.name, arg.ty, &arg.binding)],
+                };
+                for (name, ty, binding) in members {
+                    if let Some(naga::Binding::Location { location, .. }) = binding {
+                        let input = VertexInput {
+                            name: name.clone().unwrap_or_default(),
+                            ty: wgsl_type(&module.types[ty].inner),
+                        };
+                        vertex_inputs.insert(*location, input);
+                    }
+                }
+            }
+        }
+
+        Ok((
+            module,
+            Self {
+                bindings,
+                vertex_inputs,
+            },
+        ))
+    }
+
+    // Layout of all bindings the shader declares in the group, empty if there are none
+    pub fn bind_group_layout(&self, device: &wgpu::Device, group: u32) -> wgpu::BindGroupLayout {
+        let entries = self
+            .bindings
+            .range((group, 0)..=(group, u32::MAX))
+            .map(|(&(_, binding), b)| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: b.visibility,
+                ty: b.ty,
+                count: None,
+            })
+            .collect::<Vec<_>>();
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        })
+    }
+
+    // Checks that the binding is a uniform buffer with exactly the WGSL size of `T`
+    pub fn check_uniform<T: encase::ShaderType>(&self, group: u32, binding: u32) -> Result<()> {
+        let b = self.binding(group, binding)?;
+        let rust_type = std::any::type_name::<T>();
+        let rust_size = T::min_size().get();
+        match b.size {
+            Some(size) if size == rust_size => Ok(()),
+            Some(size) => bail!(
+                "Uniform '{}' at @group({group}) @binding({binding}) is {size} bytes in the shader \
+                 but {rust_type} is {rust_size} bytes",
+                b.name
+            ),
+            None => bail!(
+                "'{}' at @group({group}) @binding({binding}) is expected to be a uniform of \
+                 type {rust_type}, found {:?}",
+                b.name,
+                b.ty
+            ),
+        }
+    }
+
+    pub fn check_texture(
+        &self,
+        group: u32,
+        binding: u32,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Result<()> {
+        let b = self.binding(group, binding)?;
+        match b.ty {
+            wgpu::BindingType::Texture {
+                view_dimension: d,
+                multisampled: false,
+                ..
+            } if d == view_dimension => Ok(()),
+            ty => bail!(
+                "'{}' at @group({group}) @binding({binding}) is expected to be a {view_dimension:?} \
+                 texture, found {ty:?}",
+                b.name
+            ),
+        }
+    }
+
+    // Checks that a texture of the format can be bound to the texture binding, e.g. that it can
+    // be filtered if the layout requires it
+    pub fn check_texture_format(
+        &self,
+        group: u32,
+        binding: u32,
+        format: wgpu::TextureFormat,
+        features: wgpu::Features,
+    ) -> Result<()> {
+        let b = self.binding(group, binding)?;
+        let wgpu::BindingType::Texture { sample_type, .. } = b.ty else {
+            bail!(
+                "'{}' at @group({group}) @binding({binding}) is not a texture",
+                b.name
+            );
+        };
+        let format_sample_type = format.sample_type(None, Some(features));
+        let compatible = match (sample_type, format_sample_type) {
+            (
+                wgpu::TextureSampleType::Float { filterable: false },
+                Some(wgpu::TextureSampleType::Float { .. } | wgpu::TextureSampleType::Depth),
+            ) => true,
+            (expected, Some(found)) => expected == found,
+            (_, None) => false,
+        };
+        if !compatible {
+            bail!(
+                "'{}' at @group({group}) @binding({binding}) samples {sample_type:?}, which \
+                 {format:?} textures don't provide",
+                b.name
+            );
+        }
+        Ok(())
+    }
+
+    pub fn check_sampler(&self, group: u32, binding: u32) -> Result<()> {
+        let b = self.binding(group, binding)?;
+        match b.ty {
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering) => Ok(()),
+            ty => bail!(
+                "'{}' at @group({group}) @binding({binding}) is expected to be a filtering \
+                 sampler, found {ty:?}",
+                b.name
+            ),
+        }
+    }
+
+    // Checks that every vertex input is one of the attributes, with the matching type
+    pub fn check_vertex_inputs(&self, attributes: &[VertexAttribute]) -> Result<()> {
+        for (&location, input) in &self.vertex_inputs {
+            let Some(a) = attributes.iter().find(|a| a.location() == location) else {
+                bail!(
+                    "Vertex input '{}' at @location({location}) is not one of the attributes \
+                     {attributes:?}",
+                    input.name
+                );
+            };
+            if input.ty != a.wgsl_type() {
+                bail!(
+                    "Vertex input '{}' at @location({location}) is expected to be {:?} of type \
+                     {}, found {}",
+                    input.name,
+                    a,
+                    a.wgsl_type(),
+                    input.ty
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Checks that bind groups created for `self` can be used with `other`
+    pub fn check_same_layout(&self, other: &ShaderReflection) -> Result<()> {
+        for key in self.bindings.keys().chain(other.bindings.keys()) {
+            let (group, binding) = key;
+            match (self.bindings.get(key), other.bindings.get(key)) {
+                (Some(a), Some(b)) if a.ty == b.ty && a.visibility == b.visibility => (),
+                (a, b) => bail!(
+                    "Bind group layout changed at @group({group}) @binding({binding}): \
+                     {:?} -> {:?}",
+                    a.map(|a| (a.visibility, a.ty)),
+                    b.map(|b| (b.visibility, b.ty))
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn binding(&self, group: u32, binding: u32) -> Result<&ReflectedBinding> {
+        self.bindings
+            .get(&(group, binding))
+            .ok_or_else(|| anyhow!("Shader has no binding at @group({group}) @binding({binding})"))
+    }
+}
+
+fn wgsl_type(ty: &naga::TypeInner) -> String {
+    let scalar = |s: &naga::Scalar| match (s.kind, s.width) {
+        (naga::ScalarKind::Float, 4) => "f32".to_string(),
+        (naga::ScalarKind::Sint, 4) => "i32".to_string(),
+        (naga::ScalarKind::Uint, 4) => "u32".to_string(),
+        _ => format!("{s:?}"),
+    };
+    match ty {
+        naga::TypeInner::Scalar(s) => scalar(s),
+        naga::TypeInner::Vector { size, scalar: s } => format!("vec{}<{}>", *size as u8, scalar(s)),
+        ty => format!("{ty:?}"),
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::*;
+use rapier3d::na::DMatrix;
+
+use crate::file;
+use crate::math::Vec3;
+use crate::vertex::{PosTexCoordNormalVertex, Vertices};
+
+// Grid of heights from a grayscale image, rows go along Z and columns along X
+pub struct Heightmap {
+    rows: usize,
+    cols: usize,
+    // Row by row, from 0 (black) to 1 (white)
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub async fn from_file(file_name: &str) -> Result<Self> {
+        let data = file::read_binary_asset(file_name).await?;
+        let image = image::load_from_memory(&data)
+            .with_context(|| format!("Failed to decode heightmap {file_name}"))?
+            .into_luma16();
+        let (cols, rows) = (image.width() as usize, image.height() as usize);
+        if rows < 2 || cols < 2 {
+            bail!("Heightmap {file_name} is {cols}x{rows}, at least 2x2 pixels are required");
+        }
+
+        Ok(Self {
+            rows,
+            cols,
+            heights: image
+                .pixels()
+                .map(|p| p.0[0] as f32 / u16::MAX as f32)
+                .collect(),
+        })
+    }
+
+    fn height(&self, row: usize, col: usize) -> f32 {
+        self.heights[row * self.cols + col]
+    }
+
+    // Heights scaled to `size.y`, laid out as Rapier heightfields expect them
+    pub fn to_matrix(&self, size: Vec3) -> DMatrix<f32> {
+        DMatrix::from_fn(self.rows, self.cols, |row, col| {
+            self.height(row, col) * size.y
+        })
+    }
+
+    // Vertices and indices of the terrain split into chunks of at most `chunk_cells` cells per
+    // side. The terrain is centered on the origin horizontally, spans `size` and starts at 0
+    // height, matching the heightfield collider of the same size. Texture coordinates go from
+    // 0 to 1 over the whole terrain.
+    pub fn chunks(&self, size: Vec3, chunk_cells: usize) -> Vec<(Vertices, Vec<u32>)> {
+        let cell_width = size.x / (self.cols - 1) as f32;
+        let cell_depth = size.z / (self.rows - 1) as f32;
+
+        let vertex = |row: usize, col: usize| {
+            let (u, v) = (
+                col as f32 / (self.cols - 1) as f32,
+                row as f32 / (self.rows - 1) as f32,
+            );
+
+            // Central differences, one-sided at the edges
+            let (left, right) = (col.saturating_sub(1), (col + 1).min(self.cols - 1));
+            let (back, front) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+            let dx = (self.height(row, right) - self.height(row, left)) * size.y
+                / ((right - left) as f32 * cell_width);
+            let dz = (self.height(front, col) - self.height(back, col)) * size.y
+                / ((front - back) as f32 * cell_depth);
+            let normal = Vec3::new(-dx, 1.0, -dz).normalize();
+
+            PosTexCoordNormalVertex {
+                position: [
+                    (u - 0.5) * size.x,
+                    self.height(row, col) * size.y,
+                    (v - 0.5) * size.z,
+                ],
+                tex_coords: [u, v],
+                normal: normal.into(),
+            }
+        };
+
+        let mut chunks = Vec::new();
+        for row0 in (0..self.rows - 1).step_by(chunk_cells) {
+            for col0 in (0..self.cols - 1).step_by(chunk_cells) {
+                let row1 = (row0 + chunk_cells).min(self.rows - 1);
+                let col1 = (col0 + chunk_cells).min(self.cols - 1);
+                let chunk_cols = col1 - col0 + 1;
+
+                let vertices = (row0..=row1)
+                    .flat_map(|row| (col0..=col1).map(move |col| (row, col)))
+                    .map(|(row, col)| vertex(row, col))
+                    .collect::<Vertices>();
+
+                // Cells are split along the same diagonal as in Rapier heightfields
+                let mut indices = Vec::with_capacity((row1 - row0) * (col1 - col0) * 6);
+                for row in 0..row1 - row0 {
+                    for col in 0..col1 - col0 {
+                        let i00 = (row * chunk_cols + col) as u32;
+                        let i01 = i00 + 1;
+                        let i10 = i00 + chunk_cols as u32;
+                        let i11 = i10 + 1;
+                        indices.extend_from_slice(&[i00, i10, i01, i01, i10, i11]);
+                    }
+                }
+
+                chunks.push((vertices, indices));
+            }
+        }
+
+        chunks
+    }
+}
@@ -1,24 +1,111 @@
 use anyhow::*;
-use image::GenericImageView;
+use image::imageops::FilterType;
 use wgpu::util::{DeviceExt, TextureDataOrder};
 
+use crate::assets::Assets;
+use crate::block_compression;
+use crate::dds;
+use crate::equirect::EquirectToCube;
 use crate::file;
 use crate::graphics::Graphics;
+use crate::ktx2;
+use crate::math::linear_to_srgb;
 
 pub type TextureSize = (u32, u32);
 
+// Pixels of a texture as loaded from a file
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    // Including cubemap faces
+    pub layer_count: u32,
+    pub mip_level_count: u32,
+    pub cube: bool,
+    pub format: wgpu::TextureFormat,
+    // All mip levels of the first layer, then of the second one and so on
+    pub data: Vec<u8>,
+}
+
+impl TextureData {
+    pub fn expected_data_len(&self) -> usize {
+        let layer_len = (0..self.mip_level_count)
+            .map(|mip| self.mip_len(mip))
+            .sum::<usize>();
+        layer_len * self.layer_count as usize
+    }
+
+    // Bytes of a single layer at the mip level
+    pub fn mip_len(&self, mip: u32) -> usize {
+        let (block_width, block_height) = self.format.block_dimensions();
+        let block_len = self.format.block_copy_size(None).unwrap_or(0) as usize;
+        let width = (self.width >> mip).max(1).div_ceil(block_width);
+        let height = (self.height >> mip).max(1).div_ceil(block_height);
+        (width * height) as usize * block_len
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SamplerOptions {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // 1 disables anisotropic filtering, higher values (up to 16) require all filters to be linear
+    pub anisotropy: u16,
+    // Applied to all coordinates
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+impl SamplerOptions {
+    fn descriptor(&self) -> Result<wgpu::SamplerDescriptor<'static>> {
+        if !(1..=16).contains(&self.anisotropy) {
+            bail!(
+                "Anisotropy must be between 1 and 16, got {}",
+                self.anisotropy
+            );
+        }
+        let all_linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|&f| f == wgpu::FilterMode::Linear);
+        if self.anisotropy > 1 && !all_linear {
+            bail!("Anisotropic filtering requires linear filters");
+        }
+
+        Ok(wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: self.anisotropy,
+            ..Default::default()
+        })
+    }
+}
+
 pub struct Texture {
+    texture: wgpu::Texture,
     view: wgpu::TextureView,
     sampler: wgpu::Sampler,
     format: wgpu::TextureFormat,
+    view_dimension: wgpu::TextureViewDimension,
 }
 
 impl Texture {
-    pub fn new_depth(
-        gfx: &wgpu::Device,
-        format: wgpu::TextureFormat,
-        size: TextureSize,
-    ) -> Self {
+    pub fn new_depth(gfx: &wgpu::Device, format: wgpu::TextureFormat, size: TextureSize) -> Self {
         let size = wgpu::Extent3d {
             width: size.0,
             height: size.1,
@@ -37,15 +124,18 @@ impl Texture {
         });
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = gfx.create_sampler(&new_sampler_descriptor(wgpu::FilterMode::Nearest,
+        let sampler = gfx.create_sampler(&new_sampler_descriptor(
+            wgpu::FilterMode::Nearest,
             wgpu::FilterMode::Nearest,
             Some(wgpu::CompareFunction::LessEqual),
         ));
 
         Self {
+            texture,
             view,
             sampler,
             format,
+            view_dimension: wgpu::TextureViewDimension::D2,
         }
     }
 
@@ -67,7 +157,9 @@ impl Texture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
 
@@ -79,20 +171,68 @@ impl Texture {
         ));
 
         Self {
+            texture,
             view,
             sampler,
             format,
+            view_dimension: wgpu::TextureViewDimension::D2,
         }
     }
 
-    pub async fn new_2d_from_file(file_name: &str, gfx: &Graphics<'_>) -> Result<Self> {
+    // Loads a texture of any layout from a KTX2 or DDS file, a cubemap from an HDR or EXR
+    // equirectangular panorama, or a 2D texture from other image files
+    pub async fn new_from_file(
+        file_name: &str,
+        sampler: SamplerOptions,
+        gfx: &Graphics<'_>,
+        assets: &Assets,
+    ) -> Result<Self> {
         let data = file::read_binary_asset(file_name).await?;
-        Self::new_2d_from_mem(gfx, &data)
+        let extension = std::path::Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let texture = match extension.as_deref() {
+            Some("ktx2") => {
+                ktx2::decode(&data).and_then(|data| Self::new_from_data(gfx, &data, sampler))
+            }
+            Some("dds") => {
+                dds::decode(&data).and_then(|data| Self::new_from_data(gfx, &data, sampler))
+            }
+            Some("hdr" | "exr") => Self::new_cube_from_equirect(gfx, assets, &data, sampler),
+            _ => Self::new_2d_from_mem(gfx, assets, &data, sampler),
+        };
+        texture.with_context(|| format!("Failed to load {file_name}"))
     }
 
-    pub async fn new_cube_from_file(file_name: &str, gfx: &Graphics<'_>) -> Result<Self> {
-        let data = file::read_binary_asset(file_name).await?;
-        Self::new_cube_from_mem(gfx, &data)
+    pub async fn new_2d_from_file(
+        file_name: &str,
+        sampler: SamplerOptions,
+        gfx: &Graphics<'_>,
+        assets: &Assets,
+    ) -> Result<Self> {
+        let texture = Self::new_from_file(file_name, sampler, gfx, assets).await?;
+        if texture.view_dimension != wgpu::TextureViewDimension::D2 {
+            bail!("{file_name} is not a 2D texture");
+        }
+        Ok(texture)
+    }
+
+    pub async fn new_cube_from_file(
+        file_name: &str,
+        gfx: &Graphics<'_>,
+        assets: &Assets,
+    ) -> Result<Self> {
+        let texture =
+            Self::new_from_file(file_name, SamplerOptions::default(), gfx, assets).await?;
+        if texture.view_dimension != wgpu::TextureViewDimension::Cube {
+            bail!("{file_name} is not a cubemap");
+        }
+        Ok(texture)
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
     }
 
     pub fn view(&self) -> &wgpu::TextureView {
@@ -107,10 +247,35 @@ impl Texture {
         self.format
     }
 
-    fn new_2d_from_mem(gfx: &Graphics, data: &[u8]) -> Result<Self> {
+    // Mip levels are generated from the image
+    fn new_2d_from_mem(
+        gfx: &Graphics,
+        assets: &Assets,
+        data: &[u8],
+        sampler: SamplerOptions,
+    ) -> Result<Self> {
         let img = image::load_from_memory(data)?;
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        Self::new_2d_from_image(gfx, assets, &img.to_rgba8(), sampler)
+    }
+
+    // Single pixel of a linear color
+    pub fn new_2d_from_color(gfx: &Graphics, assets: &Assets, color: [f32; 4]) -> Result<Self> {
+        let [r, g, b, a] = color;
+        let pixel = [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a]
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba(pixel));
+        Self::new_2d_from_image(gfx, assets, &image, SamplerOptions::default())
+    }
+
+    // sRGB color texture, mip levels are generated from the image
+    pub fn new_2d_from_image(
+        gfx: &Graphics,
+        assets: &Assets,
+        rgba: &image::RgbaImage,
+        sampler: SamplerOptions,
+    ) -> Result<Self> {
+        let sampler = gfx.create_sampler(&sampler.descriptor()?);
+        let dimensions = rgba.dimensions();
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
@@ -118,88 +283,201 @@ impl Texture {
         };
         let format = wgpu::TextureFormat::Rgba8UnormSrgb;
 
-        let texture = gfx.create_texture_with_data(
+        let texture = gfx.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: size.max_mips(wgpu::TextureDimension::D2),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        gfx.queue().write_texture(
+            texture.as_image_copy(),
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+        assets.generate_mipmaps(gfx, &texture);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        })
+    }
+
+    // Converted on the GPU to a half float cubemap with faces a quarter of the panorama's width
+    fn new_cube_from_equirect(
+        gfx: &Graphics,
+        assets: &Assets,
+        data: &[u8],
+        sampler: SamplerOptions,
+    ) -> Result<Self> {
+        let sampler = gfx.create_sampler(&sampler.descriptor()?);
+        let mut img = image::load_from_memory(data)?.into_rgba32f();
+        // Panoramas over the size limit are downscaled, their faces being smaller anyway
+        let max_size = gfx.limits().max_texture_dimension_2d;
+        if img.width() > max_size || img.height() > max_size {
+            let scale = max_size as f32 / img.width().max(img.height()) as f32;
+            let [width, height] =
+                [img.width(), img.height()].map(|s| ((s as f32 * scale) as u32).clamp(1, max_size));
+            img = image::imageops::resize(&img, width, height, FilterType::Triangle);
+        }
+        let pixels = img
+            .as_raw()
+            .iter()
+            .map(|&c| half::f16::from_f32(c))
+            .collect::<Vec<_>>();
+
+        let panorama = gfx.create_texture_with_data(
             gfx.queue(),
             &wgpu::TextureDescriptor {
                 label: None,
-                size,
+                size: wgpu::Extent3d {
+                    width: img.width(),
+                    height: img.height(),
+                    depth_or_array_layers: 1,
+                },
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format,
+                format: EquirectToCube::FORMAT,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             },
-            TextureDataOrder::default(),
-            &rgba,
+            TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&pixels),
         );
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = gfx.create_sampler(&new_sampler_descriptor(
-            wgpu::FilterMode::Nearest,
-            wgpu::FilterMode::Nearest,
-            None,
-        ));
+        let face_size = (img.width() / 4).clamp(1, gfx.limits().max_texture_dimension_2d);
+        let texture = assets.equirect_to_cube(gfx, &panorama, face_size);
+        assets.generate_mipmaps(gfx, &texture);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: None,
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..wgpu::TextureViewDescriptor::default()
+        });
 
         Ok(Self {
+            texture,
             view,
             sampler,
-            format,
+            format: EquirectToCube::FORMAT,
+            view_dimension: wgpu::TextureViewDimension::Cube,
         })
     }
 
-    fn new_cube_from_mem(gfx: &Graphics, data: &[u8]) -> Result<Self> {
-        let image = ddsfile::Dds::read(&mut std::io::Cursor::new(&data)).unwrap();
-
-        let format = wgpu::TextureFormat::Rgba8UnormSrgb; // TODO Configurable
-
-        let size = wgpu::Extent3d {
-            width: 128,
-            height: 128,
-            depth_or_array_layers: 6,
+    // Uploads layers and mip levels as they are. Block compressed data is decompressed when the
+    // device doesn't support the format, and 32-bit floats are halved when it can't filter them.
+    fn new_from_data(gfx: &Graphics, data: &TextureData, sampler: SamplerOptions) -> Result<Self> {
+        let sampler = gfx.create_sampler(&sampler.descriptor()?);
+        let (block_width, block_height) = data.format.block_dimensions();
+        let supported = gfx.features().contains(data.format.required_features())
+            // Compressed textures must consist of whole blocks
+            && data.width.is_multiple_of(block_width)
+            && data.height.is_multiple_of(block_height);
+        let decompressed;
+        let data = match supported {
+            true => data,
+            false if data.format.required_features() == wgpu::Features::TEXTURE_COMPRESSION_BC => {
+                decompressed = block_compression::decompress(data)?;
+                &decompressed
+            }
+            false => bail!("{:?} is not supported by the device", data.format),
+        };
+        // Materials sample textures with filtering
+        let halved;
+        let data = match data.format.sample_type(None, Some(gfx.features())) {
+            Some(wgpu::TextureSampleType::Float { filterable: false }) => {
+                halved = to_half_float(data)?;
+                &halved
+            }
+            _ => data,
         };
 
-        let layer_size = wgpu::Extent3d {
-            depth_or_array_layers: 1,
-            ..size
+        let view_dimension = match (data.cube, data.layer_count) {
+            (true, 6) => wgpu::TextureViewDimension::Cube,
+            (true, _) => wgpu::TextureViewDimension::CubeArray,
+            (false, 1) => wgpu::TextureViewDimension::D2,
+            (false, _) => wgpu::TextureViewDimension::D2Array,
         };
 
         let texture = gfx.create_texture_with_data(
             gfx.queue(),
             &wgpu::TextureDescriptor {
-                size,
-                mip_level_count: layer_size.max_mips(wgpu::TextureDimension::D2),
+                size: wgpu::Extent3d {
+                    width: data.width,
+                    height: data.height,
+                    depth_or_array_layers: data.layer_count,
+                },
+                mip_level_count: data.mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format,
+                format: data.format,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 label: None,
                 view_formats: &[],
             },
-            TextureDataOrder::default(),
-            &image.data,
+            TextureDataOrder::LayerMajor,
+            &data.data,
         );
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
             label: None,
-            dimension: Some(wgpu::TextureViewDimension::Cube),
+            dimension: Some(view_dimension),
             ..wgpu::TextureViewDescriptor::default()
         });
 
-        let sampler = gfx.create_sampler(&new_sampler_descriptor(
-            wgpu::FilterMode::Linear,
-            wgpu::FilterMode::Linear,
-            None,
-        ));
-
         Ok(Self {
+            texture,
             view,
             sampler,
-            format,
+            format: data.format,
+            view_dimension,
         })
     }
 }
 
+// Same channels as 16-bit floats, which are filterable without `FLOAT32_FILTERABLE`
+fn to_half_float(texture: &TextureData) -> Result<TextureData> {
+    use wgpu::TextureFormat as F;
+
+    let format = match texture.format {
+        F::R32Float => F::R16Float,
+        F::Rg32Float => F::Rg16Float,
+        F::Rgba32Float => F::Rgba16Float,
+        f => bail!("{f:?} textures can't be filtered"),
+    };
+    let data = texture
+        .data
+        .chunks_exact(4)
+        .flat_map(|c| half::f16::from_f32(f32::from_le_bytes(c.try_into().unwrap())).to_le_bytes())
+        .collect();
+
+    Ok(TextureData {
+        width: texture.width,
+        height: texture.height,
+        layer_count: texture.layer_count,
+        mip_level_count: texture.mip_level_count,
+        cube: texture.cube,
+        format,
+        data,
+    })
+}
+
 fn new_sampler_descriptor<'a>(
     filter: wgpu::FilterMode,
     mipmap_filter: wgpu::FilterMode,
@@ -218,4 +496,4 @@ fn new_sampler_descriptor<'a>(
         compare,
         ..Default::default()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,165 @@
+use std::f32::consts::PI;
+
+use crate::ibl::Lighting;
+use crate::materials::SkyParams;
+use crate::math::Vec3;
+
+// Angle between the sun's path and the zenith, so that the noon sun is not straight overhead
+const SUN_PATH_TILT: f32 = 30.0 * PI / 180.0;
+// Direct light at noon, reddened and dimmed towards the horizon
+const SUN_LIGHT: f32 = 0.7;
+// Optical depth of the atmosphere at the zenith, from Rayleigh scattering mostly
+const ZENITH_OPTICAL_DEPTH: [f32; 3] = [0.07, 0.13, 0.28];
+const MOON_LIGHT: Vec3 = Vec3::new(0.04, 0.05, 0.08);
+const NIGHT_AMBIENT: Vec3 = Vec3::new(0.02, 0.025, 0.05);
+const NIGHT_SKY: Vec3 = Vec3::new(0.002, 0.003, 0.008);
+
+// Clock moving the sun and the moon across the sky. The sun rises at 6 in the +X direction,
+// peaks at noon towards +Z and sets at 18 in the -X direction, the moon is opposite to it.
+// The sun can also be placed by hand, which stops the clock.
+pub struct TimeOfDay {
+    // Hours since midnight
+    pub hour: f32,
+    // Real seconds a full day takes
+    pub day_length: f32,
+    pub paused: bool,
+    // Elevation and azimuth of the sun in degrees, overriding the clock while set. The azimuth
+    // goes from +X towards +Z.
+    pub manual_sun: Option<(f32, f32)>,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hour: 10.0,
+            day_length: 600.0,
+            paused: false,
+            manual_sun: None,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn update(&mut self, dt: f32) {
+        if !self.paused && self.manual_sun.is_none() && self.day_length > 0.0 {
+            self.hour = (self.hour + dt * 24.0 / self.day_length).rem_euclid(24.0);
+        }
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        if let Some((elevation, azimuth)) = self.manual_sun {
+            let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+            return Vec3::new(
+                elevation.cos() * azimuth.cos(),
+                elevation.sin(),
+                elevation.cos() * azimuth.sin(),
+            );
+        }
+        let angle = (self.hour / 24.0 - 0.25) * 2.0 * PI;
+        Vec3::new(
+            angle.cos(),
+            angle.sin() * SUN_PATH_TILT.cos(),
+            angle.sin() * SUN_PATH_TILT.sin(),
+        )
+    }
+
+    pub fn moon_direction(&self) -> Vec3 {
+        -self.sun_direction()
+    }
+
+    // 1 during the day, 0 at night, blending through twilight
+    pub fn daylight(&self) -> f32 {
+        smoothstep(-0.1, 0.2, self.sun_direction().y)
+    }
+
+    // Sunlight reaching the ground, black once the sun is below the horizon
+    pub fn sun_color(&self) -> Vec3 {
+        let sin_elevation = self.sun_direction().y;
+        let elevation = sin_elevation.asin().to_degrees();
+        if elevation < -5.0 {
+            return Vec3::zeros();
+        }
+        // Kasten and Young's approximation of the length of air the light goes through
+        let air_mass =
+            1.0 / (sin_elevation.max(0.0) + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+        let color = Vec3::from(ZENITH_OPTICAL_DEPTH).map(|d| (-d * air_mass).exp());
+        color * SUN_LIGHT * smoothstep(-5.0, 2.0, elevation)
+    }
+
+    pub fn moon_color(&self) -> Vec3 {
+        MOON_LIGHT * smoothstep(-0.05, 0.1, self.moon_direction().y)
+    }
+
+    // Lights the scene with whichever of the sun or the moon is brighter
+    pub fn lighting(&self) -> Lighting {
+        let (sun, moon) = (self.sun_color(), self.moon_color());
+        let (light_direction, light_color) = if sun.sum() >= moon.sum() {
+            (self.sun_direction(), sun)
+        } else {
+            (self.moon_direction(), moon)
+        };
+        Lighting {
+            light_direction,
+            // The environment maps are rendered from the sky, so they darken along with it
+            environment_intensity: 1.0,
+            light_color,
+            ambient: NIGHT_AMBIENT * (1.0 - self.daylight()),
+        }
+    }
+
+    // Updates the sun, the moon and the night sky, leaving the rest of the parameters as is
+    pub fn sky_params(&self, params: SkyParams) -> SkyParams {
+        SkyParams {
+            sun_direction: self.sun_direction(),
+            moon_direction: self.moon_direction(),
+            night_color: NIGHT_SKY * (1.0 - self.daylight()),
+            ..params
+        }
+    }
+
+    pub fn show_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.paused, "Paused");
+            let hours = self.hour as u32;
+            let minutes = (self.hour.fract() * 60.0) as u32;
+            ui.label(format!("{hours:02}:{minutes:02}"));
+        });
+        ui.add_enabled_ui(self.manual_sun.is_none(), |ui| {
+            ui.add(egui::Slider::new(&mut self.hour, 0.0..=24.0).text("Hour"));
+            ui.add(
+                egui::Slider::new(&mut self.day_length, 10.0..=3600.0)
+                    .logarithmic(true)
+                    .suffix(" s")
+                    .text("Day length"),
+            );
+        });
+
+        let mut manual = self.manual_sun.is_some();
+        if ui.checkbox(&mut manual, "Manual sun").changed() {
+            // Starts from where the clock put the sun
+            self.manual_sun = manual.then(|| {
+                let dir = self.sun_direction();
+                let elevation = dir.y.clamp(-1.0, 1.0).asin().to_degrees();
+                let azimuth = dir.z.atan2(dir.x).to_degrees();
+                (elevation, azimuth)
+            });
+        }
+        if let Some((elevation, azimuth)) = &mut self.manual_sun {
+            ui.add(
+                egui::Slider::new(elevation, -90.0..=90.0)
+                    .suffix("°")
+                    .text("Sun elevation"),
+            );
+            ui.add(
+                egui::Slider::new(azimuth, -180.0..=180.0)
+                    .suffix("°")
+                    .text("Sun azimuth"),
+            );
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
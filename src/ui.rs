@@ -0,0 +1,114 @@
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::graphics::Graphics;
+
+// egui overlay rendered on top of the final frame
+pub struct Ui {
+    ctx: egui::Context,
+    winit_state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+}
+
+impl Ui {
+    pub fn new(gfx: &Graphics, window: &Window) -> Self {
+        let ctx = egui::Context::default();
+        let winit_state = egui_winit::State::new(
+            ctx.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(gfx.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(gfx, gfx.surface_texture_format(), None, 1, false);
+
+        Self {
+            ctx,
+            winit_state,
+            renderer,
+        }
+    }
+
+    pub fn ctx(&self) -> &egui::Context {
+        &self.ctx
+    }
+
+    // Returns true if the event was consumed by the UI and should not be passed further.
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        let _ = self.winit_state.on_window_event(window, event);
+
+        // Not relying on `EventResponse::consumed` because egui always consumes Tab, which we use
+        // for toggling camera control. Releases are always let through so that keys don't get stuck.
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                event.state.is_pressed() && self.ctx.wants_keyboard_input()
+            }
+            WindowEvent::MouseInput { state, .. } => {
+                state.is_pressed() && self.ctx.wants_pointer_input()
+            }
+            _ => false,
+        }
+    }
+
+    // Mouse motion is received as device events, which don't go through `handle_event`
+    pub fn is_using_pointer(&self) -> bool {
+        self.ctx.is_using_pointer()
+    }
+
+    pub fn begin_frame(&mut self, window: &Window) {
+        let input = self.winit_state.take_egui_input(window);
+        self.ctx.begin_pass(input);
+    }
+
+    pub fn render(&mut self, gfx: &Graphics, window: &Window) {
+        let output = self.ctx.end_pass();
+        self.winit_state
+            .handle_platform_output(window, output.platform_output);
+
+        let paint_jobs = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [gfx.surface_size().width, gfx.surface_size().height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(gfx, gfx.queue(), *id, delta);
+        }
+
+        let profiler_scope = gfx.profiler().begin_pass("UI");
+        let mut encoder =
+            gfx.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut cmd_buffers =
+            self.renderer
+                .update_buffers(gfx, gfx.queue(), &mut encoder, &paint_jobs, &screen);
+
+        {
+            let mut pass = encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: gfx.surface_tex_view(),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: gfx.profiler().timestamp_writes(&profiler_scope),
+                })
+                .forget_lifetime();
+            self.renderer.render(&mut pass, &paint_jobs, &screen);
+        }
+
+        cmd_buffers.push(encoder.finish());
+        gfx.queue().submit(cmd_buffers);
+        gfx.profiler().end_pass(profiler_scope);
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}
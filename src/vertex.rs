@@ -1,34 +1,227 @@
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+use std::fmt;
+
+use anyhow::*;
+
+// Per-vertex data a mesh can carry. Each attribute is stored in its own buffer and bound at a
+// fixed shader location, so materials only bind the attributes they use.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VertexAttribute {
+    Position,
+    TexCoords,
+    Normal,
+    // xyz is the tangent, w the sign of the bitangent
+    Tangent,
+    // Second UV set, e.g. for lightmaps
+    TexCoords1,
+    // Linear RGBA
+    Color,
+    // Indices of the 4 joints influencing the vertex
+    Joints,
+    Weights,
+}
+
+impl VertexAttribute {
+    pub const ALL: [VertexAttribute; 8] = [
+        VertexAttribute::Position,
+        VertexAttribute::TexCoords,
+        VertexAttribute::Normal,
+        VertexAttribute::Tangent,
+        VertexAttribute::TexCoords1,
+        VertexAttribute::Color,
+        VertexAttribute::Joints,
+        VertexAttribute::Weights,
+    ];
+
+    pub fn location(self) -> u32 {
+        self as u32
+    }
+
+    pub const fn format(self) -> wgpu::VertexFormat {
+        match self {
+            VertexAttribute::Position | VertexAttribute::Normal => wgpu::VertexFormat::Float32x3,
+            VertexAttribute::TexCoords | VertexAttribute::TexCoords1 => {
+                wgpu::VertexFormat::Float32x2
+            }
+            VertexAttribute::Tangent | VertexAttribute::Color | VertexAttribute::Weights => {
+                wgpu::VertexFormat::Float32x4
+            }
+            VertexAttribute::Joints => wgpu::VertexFormat::Uint16x4,
+        }
+    }
+
+    // Type of the matching vertex shader input
+    pub fn wgsl_type(self) -> &'static str {
+        match self {
+            VertexAttribute::Position | VertexAttribute::Normal => "vec3<f32>",
+            VertexAttribute::TexCoords | VertexAttribute::TexCoords1 => "vec2<f32>",
+            VertexAttribute::Tangent | VertexAttribute::Color | VertexAttribute::Weights => {
+                "vec4<f32>"
+            }
+            VertexAttribute::Joints => "vec4<u32>",
+        }
+    }
+
+    // Layout of the buffer holding the attribute, for the vertex buffer slot it's bound to
+    pub fn buffer_layout(self) -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: [wgpu::VertexAttribute; 8] = {
+            let mut attributes = [wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32,
+                offset: 0,
+                shader_location: 0,
+            }; 8];
+            let mut i = 0;
+            while i < attributes.len() {
+                attributes[i].format = VertexAttribute::ALL[i].format();
+                attributes[i].shader_location = VertexAttribute::ALL[i] as u32;
+                i += 1;
+            }
+            attributes
+        };
+
+        wgpu::VertexBufferLayout {
+            array_stride: self.format().size(),
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: std::slice::from_ref(&ATTRIBUTES[self as usize]),
+        }
+    }
+
+    // Vertex buffer layouts of the attributes, in the order of their slots
+    pub fn buffer_layouts(attributes: &[Self]) -> Vec<wgpu::VertexBufferLayout<'static>> {
+        attributes.iter().map(|a| a.buffer_layout()).collect()
+    }
+}
+
+// Set of attributes a mesh has
+#[derive(Copy, Clone, PartialEq, Eq, Default)]
+pub struct VertexLayout(u8);
+
+impl VertexLayout {
+    pub fn contains(self, attribute: VertexAttribute) -> bool {
+        self.0 & 1 << attribute as u8 != 0
+    }
+
+    pub fn insert(&mut self, attribute: VertexAttribute) {
+        self.0 |= 1 << attribute as u8;
+    }
+
+    pub fn attributes(self) -> impl Iterator<Item = VertexAttribute> {
+        VertexAttribute::ALL
+            .into_iter()
+            .filter(move |&a| self.contains(a))
+    }
+
+    // Fails listing the required attributes the layout is missing
+    pub fn check(self, required: &[VertexAttribute]) -> Result<()> {
+        let missing = required
+            .iter()
+            .filter(|&&a| !self.contains(a))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!("Missing vertex attributes {missing:?}");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for VertexLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.attributes()).finish()
+    }
+}
+
+// Vertex attributes as separate streams. Attributes other than positions are left empty when
+// missing, otherwise they have one element per position.
+#[derive(Clone, Default)]
+pub struct Vertices {
+    pub positions: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tangents: Vec<[f32; 4]>,
+    pub tex_coords_1: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+impl Vertices {
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    // Bytes of the attribute as uploaded to its buffer, empty if missing
+    pub fn attribute_data(&self, attribute: VertexAttribute) -> &[u8] {
+        match attribute {
+            VertexAttribute::Position => bytemuck::cast_slice(&self.positions),
+            VertexAttribute::TexCoords => bytemuck::cast_slice(&self.tex_coords),
+            VertexAttribute::Normal => bytemuck::cast_slice(&self.normals),
+            VertexAttribute::Tangent => bytemuck::cast_slice(&self.tangents),
+            VertexAttribute::TexCoords1 => bytemuck::cast_slice(&self.tex_coords_1),
+            VertexAttribute::Color => bytemuck::cast_slice(&self.colors),
+            VertexAttribute::Joints => bytemuck::cast_slice(&self.joints),
+            VertexAttribute::Weights => bytemuck::cast_slice(&self.weights),
+        }
+    }
+
+    pub fn layout(&self) -> VertexLayout {
+        let mut layout = VertexLayout::default();
+        layout.insert(VertexAttribute::Position);
+        for a in VertexAttribute::ALL {
+            if !self.attribute_data(a).is_empty() {
+                layout.insert(a);
+            }
+        }
+        layout
+    }
+
+    // Checks that every attribute present has one element per vertex
+    pub fn check(&self) -> Result<()> {
+        for a in self.layout().attributes() {
+            let len = self.attribute_data(a).len() / a.format().size() as usize;
+            if len != self.len() {
+                bail!("{a:?} has {len} elements for {} vertices", self.len());
+            }
+        }
+        Ok(())
+    }
+
+    // Vertices at the given indices, in their order
+    pub fn gather(&self, indices: &[u32]) -> Self {
+        fn gather<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+            if values.is_empty() {
+                return Vec::new();
+            }
+            indices.iter().map(|&i| values[i as usize]).collect()
+        }
+
+        Self {
+            positions: gather(&self.positions, indices),
+            tex_coords: gather(&self.tex_coords, indices),
+            normals: gather(&self.normals, indices),
+            tangents: gather(&self.tangents, indices),
+            tex_coords_1: gather(&self.tex_coords_1, indices),
+            colors: gather(&self.colors, indices),
+            joints: gather(&self.joints, indices),
+            weights: gather(&self.weights, indices),
+        }
+    }
+}
+
+// Convenient for building geometry one vertex at a time
+#[derive(Copy, Clone, Debug)]
 pub struct PosTexCoordNormalVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
 }
 
-impl PosTexCoordNormalVertex {
-    pub fn buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
-        use std::mem;
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<PosTexCoordNormalVertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-            ],
+impl FromIterator<PosTexCoordNormalVertex> for Vertices {
+    fn from_iter<I: IntoIterator<Item = PosTexCoordNormalVertex>>(iter: I) -> Self {
+        let mut vertices = Self::default();
+        for v in iter {
+            vertices.positions.push(v.position);
+            vertices.tex_coords.push(v.tex_coords);
+            vertices.normals.push(v.normal);
         }
+        vertices
     }
 }
@@ -0,0 +1,46 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+use anyhow::*;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::file;
+
+// Tracks modifications of files in the assets directory
+pub struct AssetWatcher {
+    // Must be kept alive for events to keep coming
+    _watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    dir: PathBuf,
+}
+
+impl AssetWatcher {
+    pub fn new() -> Result<Self> {
+        let dir = file::assets_dir().canonicalize()?;
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events: rx,
+            dir,
+        })
+    }
+
+    // Paths relative to the assets directory of files changed since the last call
+    pub fn changed_files(&self) -> HashSet<String> {
+        self.events
+            .try_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| matches!(e.kind, EventKind::Create(_) | EventKind::Modify(_)))
+            .flat_map(|e| e.paths)
+            .filter_map(|p| {
+                p.strip_prefix(&self.dir)
+                    .ok()
+                    .map(|p| p.to_string_lossy().replace('\\', "/"))
+            })
+            .collect()
+    }
+}